@@ -0,0 +1,342 @@
+// End-to-end throughput benchmarks over large synthetic feeds.
+//
+//   cargo bench --bench throughput
+//
+// Environment:
+//   FLOOF_BENCH_ROWS      comma separated feed sizes (default 1000000). The
+//                         nightly run uses 1000000,10000000,100000000
+//   FLOOF_BENCH_OUTPUT    jsonl file results are appended to
+//                         (default target/bench/throughput.jsonl)
+//   FLOOF_BENCH_BASELINE  jsonl file from a previous run. The bench exits
+//                         non-zero if any stage is slower than the baseline
+//                         by more than FLOOF_BENCH_TOLERANCE (default 0.1)
+
+use floof::{
+    binlog::{BinReader, BinWriter},
+    client::Client,
+    engine::Engine,
+    input::CsvOptions,
+    pipeline::{self, Options},
+    transaction::{RawTransaction, Transaction, TxReader},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+type Bench = (&'static str, fn(&Path) -> u64);
+
+#[derive(Debug, Clone, Copy)]
+enum Feed {
+    // mostly deposits and withdrawals with the occasional dispute
+    Mixed,
+    // every deposit is disputed and then resolved or charged back
+    Disputes,
+}
+
+impl Feed {
+    fn name(self) -> &'static str {
+        match self {
+            Feed::Mixed => "mixed",
+            Feed::Disputes => "disputes",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BenchResult {
+    bench: String,
+    feed: String,
+    rows: u64,
+    secs: f64,
+    rows_per_sec: f64,
+    timestamp: u64,
+}
+
+// Small deterministic generator so that feeds are identical across runs
+// without pulling in a rng crate
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+fn generate(path: &Path, feed: Feed, rows: u64) {
+    let mut rng = Lcg(rows);
+    let mut wtr = BufWriter::new(File::create(path).unwrap());
+    writeln!(wtr, "type,client,tx,amount").unwrap();
+
+    let mut tx: u32 = 0;
+    let mut written = 0;
+    while written < rows {
+        let client = rng.below(u64::from(u16::MAX)) as u16;
+        let amount = rng.below(1_000_000) as f64 / 1e4;
+
+        match feed {
+            Feed::Mixed => {
+                let roll = rng.below(100);
+                if roll < 60 || tx == 0 {
+                    tx += 1;
+                    writeln!(wtr, "deposit,{},{},{}", client, tx, amount)
+                } else if roll < 90 {
+                    tx += 1;
+                    writeln!(wtr, "withdrawal,{},{},{}", client, tx, amount)
+                } else {
+                    // usually refers to another client's tx and gets
+                    // rejected, which is exercised on purpose
+                    let root = rng.below(u64::from(tx)) as u32 + 1;
+                    let ty = ["dispute", "resolve", "chargeback"]
+                        [rng.below(3) as usize];
+                    writeln!(wtr, "{},{},{},", ty, client, root)
+                }
+                .unwrap();
+                written += 1;
+            }
+            Feed::Disputes => {
+                tx += 1;
+                let settle = if rng.below(10) == 0 {
+                    "chargeback"
+                } else {
+                    "resolve"
+                };
+                write!(
+                    wtr,
+                    "deposit,{c},{t},{a}\ndispute,{c},{t},\n{s},{c},{t},\n",
+                    c = client,
+                    t = tx,
+                    a = amount,
+                    s = settle
+                )
+                .unwrap();
+                written += 3;
+            }
+        }
+    }
+    wtr.flush().unwrap();
+}
+
+fn csv_parse(path: &Path) -> u64 {
+    let mut rows = 0;
+    let mut rdr = csv::Reader::from_path(path).unwrap();
+    for raw_tx in rdr.deserialize() {
+        let _: RawTransaction = raw_tx.unwrap();
+        rows += 1;
+    }
+    rows
+}
+
+fn try_from(path: &Path) -> u64 {
+    let mut rows = 0;
+    let mut rdr = csv::Reader::from_path(path).unwrap();
+    for raw_tx in rdr.deserialize() {
+        let raw_tx: RawTransaction = raw_tx.unwrap();
+        Transaction::try_from(raw_tx).unwrap();
+        rows += 1;
+    }
+    rows
+}
+
+fn fast_parse(path: &Path) -> u64 {
+    let mut rows = 0;
+    for tx in TxReader::from_path(path).unwrap() {
+        tx.unwrap();
+        rows += 1;
+    }
+    rows
+}
+
+// mirrors what the binary does
+fn end_to_end(path: &Path) -> u64 {
+    let mut rows = 0;
+    let mut engine = Engine::new(HashMap::<u16, Client>::new());
+    for tx in TxReader::from_path(path).unwrap() {
+        let _ = engine.apply(tx.unwrap());
+        rows += 1;
+    }
+
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for client in engine.clients().values() {
+        wtr.serialize(client).unwrap();
+    }
+    rows
+}
+
+// the binary log written next to each csv feed
+fn bin_path(path: &Path) -> PathBuf {
+    path.with_extension("bin")
+}
+
+fn to_binary(path: &Path) {
+    let file = BufWriter::new(File::create(bin_path(path)).unwrap());
+    let mut wtr = BinWriter::new(file).unwrap();
+    for tx in TxReader::from_path(path).unwrap() {
+        wtr.write(&tx.unwrap()).unwrap();
+    }
+    wtr.flush().unwrap();
+}
+
+fn bin_parse(path: &Path) -> u64 {
+    let file = BufReader::new(File::open(bin_path(path)).unwrap());
+    let mut rows = 0;
+    for tx in BinReader::new(file).unwrap() {
+        tx.unwrap();
+        rows += 1;
+    }
+    rows
+}
+
+fn pipelined(path: &Path) -> u64 {
+    let mut rows = 0;
+    let mut engine = Engine::new(HashMap::<u16, Client>::new());
+    let csv = CsvOptions::default();
+    pipeline::for_each_in_path(path, &csv, Options::default(), |tx| {
+        let _ = engine.apply(tx.unwrap());
+        rows += 1;
+    })
+    .unwrap();
+    rows
+}
+
+fn time<F: FnOnce() -> u64>(f: F) -> (u64, Duration) {
+    let start = Instant::now();
+    let rows = f();
+    (rows, start.elapsed())
+}
+
+fn load(path: &Path) -> Vec<BenchResult> {
+    BufReader::new(File::open(path).unwrap())
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect()
+}
+
+// Compare against the most recent baseline entry for each bench, feed and
+// size. Returns the number of regressions
+fn regressions(results: &[BenchResult], baseline: &Path, tol: f64) -> usize {
+    let baseline = load(baseline);
+    let mut count = 0;
+    for res in results {
+        let base = baseline.iter().rev().find(|base| {
+            base.bench == res.bench
+                && base.feed == res.feed
+                && base.rows == res.rows
+        });
+
+        if let Some(base) = base {
+            let ratio = res.rows_per_sec / base.rows_per_sec;
+            if ratio < 1. - tol {
+                eprintln!(
+                    "REGRESSION {}/{}/{}: {:.0} rows/s vs baseline {:.0} \
+                    rows/s ({:+.1}%)",
+                    res.bench,
+                    res.feed,
+                    res.rows,
+                    res.rows_per_sec,
+                    base.rows_per_sec,
+                    (ratio - 1.) * 100.
+                );
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+fn main() {
+    let sizes: Vec<u64> = env::var("FLOOF_BENCH_ROWS")
+        .unwrap_or_else(|_| String::from("1000000"))
+        .split(',')
+        .map(|size| size.trim().parse().expect("bad FLOOF_BENCH_ROWS"))
+        .collect();
+    let output = env::var_os("FLOOF_BENCH_OUTPUT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("target/bench/throughput.jsonl"));
+    let tolerance = env::var("FLOOF_BENCH_TOLERANCE")
+        .map(|tol| tol.parse().expect("bad FLOOF_BENCH_TOLERANCE"))
+        .unwrap_or(0.1);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let benches: [Bench; 6] = [
+        ("csv_parse", csv_parse),
+        ("try_from", try_from),
+        ("fast_parse", fast_parse),
+        ("bin_parse", bin_parse),
+        ("end_to_end", end_to_end),
+        ("pipelined", pipelined),
+    ];
+
+    let mut results = Vec::new();
+    for &rows in sizes.iter() {
+        for &feed in [Feed::Mixed, Feed::Disputes].iter() {
+            let path = env::temp_dir().join(format!(
+                "floof_bench_{}_{}_{}.csv",
+                feed.name(),
+                rows,
+                process::id()
+            ));
+            generate(&path, feed, rows);
+            to_binary(&path);
+
+            for (bench, f) in benches.iter() {
+                let (parsed, elapsed) = time(|| f(&path));
+                let secs = elapsed.as_secs_f64();
+                let res = BenchResult {
+                    bench: bench.to_string(),
+                    feed: feed.name().to_string(),
+                    rows: parsed,
+                    secs,
+                    rows_per_sec: parsed as f64 / secs,
+                    timestamp,
+                };
+                println!(
+                    "{:>10} {:>8} {:>10} rows {:>8.3}s {:>12.0} rows/s",
+                    res.bench, res.feed, res.rows, res.secs, res.rows_per_sec
+                );
+                results.push(res);
+            }
+
+            fs::remove_file(&path).unwrap();
+            fs::remove_file(bin_path(&path)).unwrap();
+        }
+    }
+
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir).unwrap();
+    }
+    let mut out = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&output)
+        .unwrap();
+    for res in results.iter() {
+        writeln!(out, "{}", serde_json::to_string(res).unwrap()).unwrap();
+    }
+
+    if let Some(baseline) = env::var_os("FLOOF_BENCH_BASELINE") {
+        let count = regressions(&results, Path::new(&baseline), tolerance);
+        if count > 0 {
+            eprintln!("{} throughput regression(s)", count);
+            process::exit(1);
+        }
+    }
+}
@@ -1 +1,7 @@
 pub mod args;
+pub mod convert;
+pub mod statement;
+pub mod validate;
+pub mod reconcile;
+pub mod diff;
+pub mod explain;
@@ -1,15 +1,408 @@
-use std::path::PathBuf;
+use floof::{
+    engine::{DebtRecovery, DisputeShortfall, Policy, WithdrawalDisputes},
+    input::{CsvOptions, InputFormat},
+    transaction::ColumnMap,
+};
+use std::{path::PathBuf, str::FromStr};
 use structopt::StructOpt;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreKind {
+    Map,
+    Vec,
+    File,
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "map" => Ok(StoreKind::Map),
+            "vec" => Ok(StoreKind::Vec),
+            "file" => Ok(StoreKind::File),
+            _ => Err(format!("unknown client store: {}", s)),
+        }
+    }
+}
+
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(format!("delimiter must be a single ascii character: {}", s)),
+    }
+}
+
+fn parse_tolerance(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(tolerance) if tolerance.is_finite() && tolerance >= 0. => {
+            Ok(tolerance)
+        }
+        _ => Err(format!("tolerance must be a number of 0 or more: {}", s)),
+    }
+}
+
+fn parse_error_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if (0. ..=1.).contains(&rate) => Ok(rate),
+        _ => Err(format!("error rate must be a number from 0 to 1: {}", s)),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CsvArgs {
+    /// Field delimiter of csv input. Use "tab" or "\t" for tab separated
+    /// files
+    #[structopt(
+        long,
+        default_value = ",",
+        parse(try_from_str = parse_delimiter)
+    )]
+    pub delimiter: u8,
+
+    /// Csv input has no header row. Columns are taken in the order
+    /// type,client,tx,amount unless mapped with --columns
+    #[structopt(long)]
+    pub no_headers: bool,
+
+    /// Map transaction fields to csv columns by header name or zero-based
+    /// index, e.g. "type=kind,client=account,tx=2". Columns that are not
+    /// mapped are ignored, with or without --flexible
+    #[structopt(long)]
+    pub columns: Option<ColumnMap>,
+
+    /// Strip whitespace around csv headers and fields
+    #[structopt(long)]
+    pub trim: bool,
+
+    /// Accept csv rows with more or fewer fields than the header row. Not
+    /// needed for extra columns, which are always ignored
+    #[structopt(long)]
+    pub flexible: bool,
+}
+
+impl CsvArgs {
+    pub fn options(&self) -> CsvOptions {
+        CsvOptions {
+            delimiter: self.delimiter,
+            has_headers: !self.no_headers,
+            trim: self.trim,
+            flexible: self.flexible,
+            columns: self.columns.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct PolicyArgs {
+    /// What disputing a withdrawal does: "refund" holds the withdrawn
+    /// amount while the dispute is open and pays it back on chargeback,
+    /// "not-disputable" rejects disputes of withdrawals
+    #[structopt(long, default_value = "refund")]
+    pub withdrawal_disputes: WithdrawalDisputes,
+
+    /// What disputing a deposit whose funds are no longer available does:
+    /// "reject" refuses the dispute, "hold-available" holds what is left,
+    /// "allow-negative" holds it all, leaving the client in debt
+    #[structopt(long, default_value = "reject")]
+    pub dispute_shortfall: DisputeShortfall,
+
+    /// How much of a deposit to a client in debt pays it off: "first" all
+    /// of it, "share:<0 to 1>" that share, "never" none
+    #[structopt(long, default_value = "first")]
+    pub debt_recovery: DebtRecovery,
+
+    /// Release what an authorization still reserves once this many more
+    /// transactions have been processed. Authorizations never expire by
+    /// default
+    #[structopt(long)]
+    pub authorization_ttl: Option<u64>,
+}
+
+impl PolicyArgs {
+    pub fn policy(&self) -> Policy {
+        Policy {
+            withdrawal_disputes: self.withdrawal_disputes,
+            dispute_shortfall: self.dispute_shortfall,
+            debt_recovery: self.debt_recovery,
+            authorization_ttl: self.authorization_ttl,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Convert a transaction file between csv, jsonl and the binary log
+    Convert {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+
+        /// Input format: csv, jsonl or bin. Detected from the extension by
+        /// default
+        #[structopt(long)]
+        from: Option<InputFormat>,
+
+        /// Output format: csv, jsonl or bin. Detected from the extension by
+        /// default
+        #[structopt(long)]
+        to: Option<InputFormat>,
+
+        #[structopt(flatten)]
+        csv: CsvArgs,
+    },
+
+    /// Show every transaction of one or all clients with its effect on
+    /// their balances, in input order
+    Statement {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Only show this client
+        #[structopt(long)]
+        client: Option<u16>,
+
+        /// Input format: csv, jsonl or bin. Detected from the extension by
+        /// default
+        #[structopt(long)]
+        input_format: Option<InputFormat>,
+
+        #[structopt(flatten)]
+        csv: CsvArgs,
+
+        #[structopt(flatten)]
+        policy: PolicyArgs,
+    },
+
+    /// Check that a file would process cleanly without producing balances.
+    /// Every problem found is printed, and the exit code is 1 if the share
+    /// of rows with problems exceeds --max-error-rate
+    Validate {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Validate against the state saved with --save-snapshot instead of
+        /// an empty one
+        #[structopt(long, parse(from_os_str))]
+        snapshot: Option<PathBuf>,
+
+        /// Highest acceptable share of rows with problems, from 0 to 1
+        #[structopt(
+            long,
+            default_value = "0",
+            parse(try_from_str = parse_error_rate)
+        )]
+        max_error_rate: f64,
+
+        /// Input format: csv, jsonl or bin. Detected from the extension by
+        /// default
+        #[structopt(long)]
+        input_format: Option<InputFormat>,
+
+        #[structopt(flatten)]
+        csv: CsvArgs,
+
+        #[structopt(flatten)]
+        policy: PolicyArgs,
+    },
+
+    /// Compare client balances with those expected by another system.
+    /// Missing and extra clients and balances that do not match are
+    /// printed, and the exit code is 1 if there are any
+    Reconcile {
+        /// Output of an earlier run, or with --run the transactions to
+        /// process
+        #[structopt(parse(from_os_str))]
+        actual: PathBuf,
+
+        /// Expected balances, a csv with the same columns as the output
+        #[structopt(parse(from_os_str))]
+        expected: PathBuf,
+
+        /// Process the transactions in <actual> and compare the resulting
+        /// balances
+        #[structopt(long)]
+        run: bool,
+
+        /// Largest difference in available, held or total that still
+        /// counts as a match
+        #[structopt(
+            long,
+            default_value = "0",
+            parse(try_from_str = parse_tolerance)
+        )]
+        tolerance: f64,
+
+        /// With --run, open accounts with the balances in this csv first,
+        /// as --opening-balances does for a normal run
+        #[structopt(long, parse(from_os_str), requires("run"))]
+        opening_balances: Option<PathBuf>,
+
+        /// With --run, start from the state saved with --save-snapshot.
+        /// Clients in it take precedence over --opening-balances
+        #[structopt(long, parse(from_os_str), requires("run"))]
+        snapshot: Option<PathBuf>,
+
+        /// Input format with --run: csv, jsonl or bin. Detected from the
+        /// extension by default
+        #[structopt(long)]
+        input_format: Option<InputFormat>,
+
+        #[structopt(flatten)]
+        csv: CsvArgs,
+
+        #[structopt(flatten)]
+        policy: PolicyArgs,
+    },
+
+    /// Rerun the transactions and tell how one client ended up with its
+    /// balances and status: what was applied, what was rejected and why,
+    /// and what its held funds and status are down to
+    Explain {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Client to explain
+        #[structopt(long)]
+        client: u16,
+
+        /// Input format: csv, jsonl or bin. Detected from the extension by
+        /// default
+        #[structopt(long)]
+        input_format: Option<InputFormat>,
+
+        #[structopt(flatten)]
+        csv: CsvArgs,
+
+        #[structopt(flatten)]
+        policy: PolicyArgs,
+    },
+
+    /// List the clients added, removed or changed between two outputs or
+    /// snapshots (.json files) with their fields before and after. The
+    /// exit code is 1 if there are any
+    Diff {
+        #[structopt(parse(from_os_str))]
+        before: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        after: PathBuf,
+
+        /// Also compare the dispute states of transactions. Needs two
+        /// snapshots
+        #[structopt(long)]
+        disputes: bool,
+
+        /// Print the differences as json
+        #[structopt(long)]
+        json: bool,
+    },
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "floof",
     about = "transaction processor"
 )]
 pub struct Args {
+    /// Transactions to process. Required unless running a subcommand
     #[structopt(parse(from_os_str))]
-    pub tx_csv: PathBuf,
+    pub tx_csv: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
 
     #[structopt(short, long)] 
     pub verbose: bool,
+
+    /// Input format: csv, jsonl or bin. Detected from the file extension
+    /// by default
+    #[structopt(long)]
+    pub input_format: Option<InputFormat>,
+
+    #[structopt(flatten)]
+    pub csv: CsvArgs,
+
+    #[structopt(flatten)]
+    pub policy: PolicyArgs,
+
+    /// Client account storage: map, vec or file
+    #[structopt(long, default_value = "map")]
+    pub store: StoreKind,
+
+    /// Csv file backing the file store. Balances are loaded from it before
+    /// processing and written back afterwards
+    #[structopt(
+        long,
+        parse(from_os_str),
+        required_if("store", "file")
+    )]
+    pub store_path: Option<PathBuf>,
+
+    /// Read, parse and apply transactions on separate threads
+    #[structopt(long)]
+    pub pipeline: bool,
+
+    /// Parser threads in pipeline mode. Defaults to the number of cpus
+    #[structopt(long, requires("pipeline"))]
+    pub workers: Option<usize>,
+
+    /// Open accounts with the balances in this csv. It needs the client,
+    /// available, held, total and locked columns, and may have status and
+    /// debt columns. Clients in --snapshot take precedence
+    #[structopt(long, parse(from_os_str))]
+    pub opening_balances: Option<PathBuf>,
+
+    /// Start from the state saved with --save-snapshot
+    #[structopt(long, parse(from_os_str))]
+    pub snapshot: Option<PathBuf>,
+
+    /// Save clients and disputable transactions as json after the run, so
+    /// that a later run or validate can carry on from them
+    #[structopt(long, parse(from_os_str))]
+    pub save_snapshot: Option<PathBuf>,
+
+    /// Print a summary of the run to stderr
+    #[structopt(long)]
+    pub stats: bool,
+
+    /// Write the run summary as json to this file
+    #[structopt(long, parse(from_os_str))]
+    pub stats_json: Option<PathBuf>,
+
+    /// Write the clients that owe money to this csv file
+    #[structopt(long, parse(from_os_str))]
+    pub debtors: Option<PathBuf>,
+
+    /// Write the admin transactions that were applied to this csv file
+    #[structopt(long, parse(from_os_str))]
+    pub audit: Option<PathBuf>,
+
+    /// Write what happened to the accounts, one json event per line, to
+    /// this file
+    #[structopt(long, parse(from_os_str))]
+    pub events: Option<PathBuf>,
+
+    /// Instead of the final balances, write a row with the new balances
+    /// every time a transaction changes a client's, numbered in order
+    #[structopt(long)]
+    pub cdc: bool,
+
+    /// Stop after the row on this line of the input, or this many rows of
+    /// binary input, and output the balances as they were then
+    #[structopt(long, conflicts_with = "until-tx")]
+    pub until_line: Option<u64>,
+
+    /// Stop after the first row with this transaction id and output the
+    /// balances as they were then
+    #[structopt(long)]
+    pub until_tx: Option<u32>,
+
+    /// Print the state of this client to stderr after every transaction
+    /// that names it
+    #[structopt(long)]
+    pub step: Option<u16>,
 }
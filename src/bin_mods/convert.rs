@@ -0,0 +1,74 @@
+use floof::{
+    binlog::BinWriter,
+    input::{self, CsvOptions, InputFormat},
+    transaction::{RawTransaction, Transaction},
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+enum Sink {
+    Csv(Box<csv::Writer<BufWriter<File>>>),
+    JsonLines(BufWriter<File>),
+    Binary(BinWriter<BufWriter<File>>),
+}
+
+impl Sink {
+    fn create(path: &Path, format: InputFormat) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match format {
+            InputFormat::Csv => {
+                Sink::Csv(Box::new(csv::Writer::from_writer(file)))
+            }
+            InputFormat::JsonLines => Sink::JsonLines(file),
+            InputFormat::Binary => Sink::Binary(BinWriter::new(file)?),
+        })
+    }
+
+    fn write(&mut self, tx: &Transaction) -> Result<(), Box<dyn Error>> {
+        match self {
+            Sink::Csv(wtr) => wtr.serialize(RawTransaction::from(tx))?,
+            Sink::JsonLines(wtr) => {
+                serde_json::to_writer(&mut *wtr, &RawTransaction::from(tx))?;
+                wtr.write_all(b"\n")?;
+            }
+            Sink::Binary(wtr) => wtr.write(tx)?,
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Csv(wtr) => wtr.flush(),
+            Sink::JsonLines(wtr) => wtr.flush(),
+            Sink::Binary(wtr) => wtr.flush(),
+        }
+    }
+}
+
+// Copy every transaction from `input` to `output`, converting between
+// formats. Stops at the first row that cannot be read so that a conversion
+// never silently drops transactions. Returns the number of rows converted.
+// Csv output always uses the default dialect
+pub fn convert(
+    input: &Path,
+    from: Option<InputFormat>,
+    csv: &CsvOptions,
+    output: &Path,
+    to: Option<InputFormat>,
+) -> Result<u64, Box<dyn Error>> {
+    let to = to.unwrap_or_else(|| InputFormat::detect(output));
+    let source = input::open(input, from, csv)?;
+    let mut sink = Sink::create(output, to)?;
+
+    let mut rows = 0;
+    for tx in source {
+        sink.write(&tx?)?;
+        rows += 1;
+    }
+    sink.flush()?;
+    Ok(rows)
+}
@@ -0,0 +1,30 @@
+use floof::{
+    diff::{self, Diff, State},
+    snapshot::Snapshot,
+};
+use std::{error::Error, ffi::OsStr, fs::File, io::BufReader, path::Path};
+
+// Snapshots are told from outputs by their .json extension
+fn load(path: &Path) -> Result<State, Box<dyn Error>> {
+    if path.extension() == Some(OsStr::new("json")) {
+        Ok(State::from_snapshot(&Snapshot::load(path)?))
+    } else {
+        Ok(State::from_output(BufReader::new(File::open(path)?))?)
+    }
+}
+
+// Compare two outputs or snapshots. Dispute states can only be compared
+// between snapshots
+pub fn diff(
+    before: &Path,
+    after: &Path,
+    disputes: bool,
+) -> Result<Diff, Box<dyn Error>> {
+    let (before, after) = (load(before)?, load(after)?);
+    if disputes
+        && (before.transactions.is_none() || after.transactions.is_none())
+    {
+        return Err("--disputes needs two snapshots".into());
+    }
+    Ok(diff::diff(&before, &after, disputes))
+}
@@ -0,0 +1,26 @@
+use floof::{
+    client::Client,
+    engine::{Engine, Policy},
+    explain::Explainer,
+    input::{self, CsvOptions, InputFormat},
+};
+use std::{collections::HashMap, error::Error, path::Path};
+
+// Rerun `input` and print what happened to `client`
+pub fn explain(
+    input: &Path,
+    from: Option<InputFormat>,
+    csv: &CsvOptions,
+    policy: Policy,
+    client: u16,
+) -> Result<(), Box<dyn Error>> {
+    let engine = Engine::new(HashMap::<u16, Client>::new()).with_policy(policy);
+    let mut explainer = Explainer::new(engine, client);
+    for (line, row) in input::open(input, from, csv)?.numbered() {
+        explainer.apply(line, row);
+    }
+    for note in explainer.finish() {
+        println!("{}", note);
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use floof::{
+    client::Client,
+    engine::{Engine, Policy},
+    input::{self, CsvOptions, InputFormat},
+    opening,
+    reconcile::{self, Balance, Report},
+    snapshot::Snapshot,
+    store::ClientStore,
+};
+use log::warn;
+use std::{
+    collections::HashMap, error::Error, fs::File, io::BufReader, path::Path,
+};
+
+// Where the balances to check come from
+pub enum Actual<'a> {
+    // output of an earlier run
+    Output(&'a Path),
+    // transactions to process first, skipping rows that cannot be read or
+    // are rejected with a warning like in a normal run. The accounts start
+    // out empty unless opened from `opening_balances` or `snapshot`
+    Run {
+        input: &'a Path,
+        from: Option<InputFormat>,
+        csv: &'a CsvOptions,
+        policy: Policy,
+        opening_balances: Option<&'a Path>,
+        snapshot: Option<&'a Path>,
+    },
+}
+
+// Compare the balances in `actual` with those in the `expected` csv
+pub fn reconcile(
+    actual: Actual,
+    expected: &Path,
+    tolerance: f64,
+) -> Result<Report, Box<dyn Error>> {
+    let expected = reconcile::read(BufReader::new(File::open(expected)?))?;
+    let actual = match actual {
+        Actual::Output(path) => {
+            reconcile::read(BufReader::new(File::open(path)?))?
+        }
+        Actual::Run {
+            input,
+            from,
+            csv,
+            policy,
+            opening_balances,
+            snapshot,
+        } => {
+            let mut clients = HashMap::<u16, Client>::new();
+            if let Some(path) = opening_balances {
+                opening::seed(&mut clients, opening::load(path)?);
+            }
+            let mut engine = match snapshot {
+                Some(path) => Snapshot::load(path)?.restore(clients)?,
+                None => Engine::new(clients),
+            }
+            .with_policy(policy);
+            for tx in input::open(input, from, csv)? {
+                match tx {
+                    Ok(tx) => {
+                        if let Err(e) = engine.apply(tx) {
+                            warn!("{}", e);
+                        }
+                    }
+                    Err(e) => warn!("{}", e),
+                }
+            }
+            ClientStore::iter(engine.clients())
+                .map(Balance::from)
+                .collect()
+        }
+    };
+    Ok(reconcile::reconcile(&expected, &actual, tolerance))
+}
@@ -0,0 +1,41 @@
+use floof::{
+    client::Client,
+    engine::{Engine, Policy},
+    input::{self, CsvOptions, InputFormat},
+    statement,
+};
+use log::warn;
+use std::{collections::HashMap, error::Error, io, path::Path};
+
+// Write the statement of `client`, or of every client, for the transactions
+// in `input` to stdout. Rows that cannot be read are skipped with a warning
+// like in a normal run
+pub fn statement(
+    input: &Path,
+    from: Option<InputFormat>,
+    csv: &CsvOptions,
+    policy: Policy,
+    client: Option<u16>,
+) -> Result<(), Box<dyn Error>> {
+    let mut engine =
+        Engine::new(HashMap::<u16, Client>::new()).with_policy(policy);
+    let mut wtr = csv::Writer::from_writer(io::stdout());
+
+    for tx in input::open(input, from, csv)? {
+        let tx = match tx {
+            Ok(tx) => tx,
+            Err(e) => {
+                warn!("{}", e);
+                continue;
+            }
+        };
+
+        for entry in statement::apply(&mut engine, tx) {
+            if client.unwrap_or(entry.client) == entry.client {
+                wtr.serialize(entry)?;
+            }
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use floof::{
+    binlog::BinReader,
+    client::Client,
+    engine::{Engine, Policy},
+    input::{self, CsvOptions, InputFormat, JsonLinesReader},
+    snapshot::Snapshot,
+    transaction::{RawReader, Transaction},
+    validate::{Report, Validator},
+};
+use std::{
+    collections::HashMap, convert::TryFrom, error::Error, fs::File,
+    io::BufReader, path::Path,
+};
+
+// Check every row of `input` against an empty state, or the state saved in
+// `snapshot`. Csv and jsonl rows go through RawTransaction and
+// Transaction::try_from like in a normal run
+pub fn validate(
+    input: &Path,
+    from: Option<InputFormat>,
+    csv: &CsvOptions,
+    policy: Policy,
+    snapshot: Option<&Path>,
+) -> Result<Report, Box<dyn Error>> {
+    let clients = HashMap::<u16, Client>::new();
+    let engine = match snapshot {
+        Some(path) => Snapshot::load(path)?.restore(clients)?,
+        None => Engine::new(clients),
+    }
+    .with_policy(policy);
+    let mut validator = Validator::new(engine);
+
+    match from.unwrap_or_else(|| InputFormat::detect(input)) {
+        InputFormat::Csv => {
+            let mut rdr = RawReader::with_columns(
+                csv.reader_from_path(input)?,
+                &csv.columns,
+            )?;
+            while let Some(row) = rdr.next() {
+                let line = rdr.line();
+                validator.check(line, row.and_then(Transaction::try_from));
+            }
+        }
+        InputFormat::JsonLines => {
+            let file = BufReader::new(File::open(input)?);
+            let mut rdr = JsonLinesReader::new(file);
+            while let Some(row) = rdr.next() {
+                let line = Some(rdr.line());
+                match row {
+                    // the line is reported separately
+                    Err(input::Error::Line { source, .. }) => {
+                        validator.check(line, Err(source))
+                    }
+                    row => validator.check(line, row),
+                }
+            }
+        }
+        InputFormat::Binary => {
+            let file = BufReader::new(File::open(input)?);
+            // binary records have no lines. Read errors name the record
+            for row in BinReader::new(file)? {
+                validator.check(None, row);
+            }
+        }
+    }
+    Ok(validator.into_report())
+}
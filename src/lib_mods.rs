@@ -1,2 +1,23 @@
 pub mod transaction;
 pub mod client;
+pub mod store;
+pub mod engine;
+pub mod pipeline;
+pub mod input;
+pub mod binlog;
+pub mod statement;
+pub mod stats;
+pub mod snapshot;
+pub mod validate;
+pub mod debtors;
+pub mod opening;
+pub mod reconcile;
+pub mod diff;
+pub mod replay;
+pub mod explain;
+pub mod events;
+pub mod cdc;
+
+// Slack for comparing amounts that went through f64 arithmetic, e.g. when
+// disputes of 0.1 and 0.2 settle a deposit of 0.3
+pub(crate) const EPSILON: f64 = 1e-9;
@@ -0,0 +1,626 @@
+use crate::transaction::{AdminAction, Transaction};
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, Read, Write},
+    result,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        crc32, decode, seal, BinReader, BinWriter, Error, HEADER_LEN,
+        RECORD_LEN, TAG_ADMIN,
+    };
+    use crate::transaction::{AdminAction, Transaction};
+
+    fn sample() -> Vec<Transaction> {
+        vec![
+            Transaction::Deposit {
+                client: u16::MAX,
+                tx: u32::MAX,
+                amount: 1.2345,
+                dispute: false,
+            },
+            Transaction::Withdrawal {
+                client: 0,
+                tx: 2,
+                amount: 0.1 + 0.2,
+                dispute: false,
+            },
+            Transaction::Dispute {
+                client: 3,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Resolve {
+                client: 3,
+                tx: 1,
+                amount: Some(0.5),
+            },
+            Transaction::Chargeback {
+                client: 3,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Authorize {
+                client: 4,
+                tx: 5,
+                amount: 2.5,
+            },
+            Transaction::Capture {
+                client: 4,
+                tx: 5,
+                amount: Some(1.),
+            },
+            Transaction::Void { client: 4, tx: 5 },
+            Transaction::Admin {
+                client: 4,
+                tx: 6,
+                action: AdminAction::Freeze,
+                actor: None,
+                reason: None,
+            },
+        ]
+    }
+
+    fn encode(txs: &[Transaction]) -> Vec<u8> {
+        let mut wtr = BinWriter::new(Vec::new()).unwrap();
+        for tx in txs {
+            wtr.write(tx).unwrap();
+        }
+        wtr.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_binlog_round_trip() {
+        let bytes = encode(&sample());
+        assert_eq!(bytes.len(), HEADER_LEN + 9 * RECORD_LEN);
+
+        let txs: Vec<Transaction> = BinReader::new(&bytes[..])
+            .unwrap()
+            .map(|tx| tx.unwrap())
+            .collect();
+        assert_eq!(txs, sample());
+    }
+
+    #[test]
+    fn test_binlog_corrupt_record() {
+        let mut bytes = encode(&sample());
+        bytes[HEADER_LEN + RECORD_LEN + 3] ^= 0xFF;
+
+        let txs: Vec<_> = BinReader::new(&bytes[..]).unwrap().collect();
+        assert!(txs[0].is_ok());
+        match txs[1] {
+            Err(Error::Checksum(1)) => (),
+            ref res => panic!("expected Error::Checksum, got {:?}", res),
+        }
+        assert!(txs[2].is_ok());
+    }
+
+    #[test]
+    fn test_binlog_corrupt_fields() {
+        // records with a valid checksum but contents no writer produces
+        let record = |tag: u8, flags: u8, action: u8| {
+            let mut rec = [0u8; RECORD_LEN];
+            rec[0] = tag;
+            rec[1] = flags;
+            rec[8] = action;
+            seal(&mut rec);
+            rec
+        };
+
+        match decode(3, &record(TAG_ADMIN, 0, 9)) {
+            Err(Error::BadAction(3, 9)) => (),
+            res => panic!("expected Error::BadAction, got {:?}", res),
+        }
+        match decode(4, &record(42, 0, 0)) {
+            Err(Error::BadTag(4, 42)) => (),
+            res => panic!("expected Error::BadTag, got {:?}", res),
+        }
+        for &tag in [1, 2, 6].iter() {
+            match decode(5, &record(tag, 0, 0)) {
+                Err(Error::MissingAmount(5)) => (),
+                res => {
+                    panic!("expected Error::MissingAmount, got {:?}", res)
+                }
+            }
+        }
+        assert!(decode(6, &record(3, 0, 0)).is_ok());
+    }
+
+    #[test]
+    fn test_binlog_truncated() {
+        let bytes = encode(&sample());
+        let txs: Vec<_> =
+            BinReader::new(&bytes[..bytes.len() - 1]).unwrap().collect();
+        assert_eq!(txs.len(), 9);
+        match txs[8] {
+            Err(Error::Truncated(8)) => (),
+            ref res => panic!("expected Error::Truncated, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_binlog_admin_notes() {
+        let admin = Transaction::Admin {
+            client: 1,
+            tx: 7,
+            action: AdminAction::Unlock,
+            actor: Some(String::from("alice")),
+            // more than one note record, and not split on a char boundary
+            reason: Some(String::from("chargeback reviewed — all clear")),
+        };
+        let deposit = Transaction::Deposit {
+            client: 1,
+            tx: 8,
+            amount: 1.,
+            dispute: false,
+        };
+        let txs = vec![admin, deposit];
+        let bytes = encode(&txs);
+        // actor takes one note record and the reason three
+        assert_eq!(bytes.len(), HEADER_LEN + 6 * RECORD_LEN);
+        let read: Vec<Transaction> = BinReader::new(&bytes[..])
+            .unwrap()
+            .map(|tx| tx.unwrap())
+            .collect();
+        assert_eq!(read, txs);
+
+        // a corrupt note fails the admin transaction only
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER_LEN + 2 * RECORD_LEN + 5] ^= 0xFF;
+        let read: Vec<_> = BinReader::new(&corrupt[..]).unwrap().collect();
+        assert_eq!(read.len(), 2);
+        match read[0] {
+            Err(Error::Checksum(2)) => (),
+            ref res => panic!("expected Error::Checksum, got {:?}", res),
+        }
+        assert_eq!(*read[1].as_ref().unwrap(), txs[1]);
+
+        // notes without their admin record, e.g. in a log split by offset
+        let mut stray = encode(&[]);
+        stray.extend(&bytes[HEADER_LEN + RECORD_LEN..]);
+        let read: Vec<_> = BinReader::new(&stray[..]).unwrap().collect();
+        assert_eq!(read.len(), 5);
+        match read[0] {
+            Err(Error::StrayNote(0)) => (),
+            ref res => panic!("expected Error::StrayNote, got {:?}", res),
+        }
+        assert!(read[4].is_ok());
+    }
+
+    #[test]
+    fn test_binlog_bad_header() {
+        match BinReader::new(&b"type,client,tx,amount\n"[..]).unwrap_err() {
+            Error::BadMagic => (),
+            e => panic!("expected Error::BadMagic, got {:?}", e),
+        }
+
+        let mut bytes = encode(&[]);
+        bytes[HEADER_LEN - 1] = 99;
+        match BinReader::new(&bytes[..]).unwrap_err() {
+            Error::UnsupportedVersion(99) => (),
+            e => panic!("expected Error::UnsupportedVersion, got {:?}", e),
+        }
+    }
+}
+
+// Layout, all integers little endian:
+//
+//   header  magic "FLOOFTX" | version u8
+//   record  tag u8 | flags u8 | client u16 | tx u32 | amount f64 bits u64 |
+//           crc32 of the preceding 16 bytes u32
+//   admin   tag 9 | flags u8 | client u16 | tx u32 | action u8 |
+//           notes u16 | 5 bytes zero | crc32
+//   note    tag 10 | field u8 | len u8 | 13 bytes of utf-8 | crc32
+//
+// Records are fixed width so a log can be seeked and split by offset. The
+// actor (field 1) and reason (field 2) of an admin transaction follow it in
+// as many note records as its `notes` count says, each holding `len` bytes
+// of the text
+pub const MAGIC: &[u8; 7] = b"FLOOFTX";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 8;
+pub const RECORD_LEN: usize = 20;
+
+const FLAG_AMOUNT: u8 = 0x01;
+
+const TAG_ADMIN: u8 = 9;
+const TAG_NOTE: u8 = 10;
+const NOTE_ACTOR: u8 = 1;
+const NOTE_REASON: u8 = 2;
+const NOTE_LEN: usize = 13;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("not a floof binary transaction log")]
+    BadMagic,
+
+    #[error("unsupported binary log version {0}, expected {}", VERSION)]
+    UnsupportedVersion(u8),
+
+    #[error("record {0} failed its checksum")]
+    Checksum(u64),
+
+    #[error("record {0} has unknown type tag {1}")]
+    BadTag(u64, u8),
+
+    #[error("record {0} has unknown admin action {1}")]
+    BadAction(u64, u8),
+
+    #[error("record {0} has no amount")]
+    MissingAmount(u64),
+
+    #[error("record {0} is truncated")]
+    Truncated(u64),
+
+    #[error("record {0} is not a valid note of the admin record before it")]
+    BadNote(u64),
+
+    #[error("record {0} is a note without its admin record")]
+    StrayNote(u64),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+// CRC-32 (IEEE 802.3)
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc = (crc >> 8) ^ CRC_TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize];
+    }
+    !crc
+}
+
+fn seal(rec: &mut [u8; RECORD_LEN]) {
+    let crc = crc32(&rec[..16]);
+    rec[16..].copy_from_slice(&crc.to_le_bytes());
+}
+
+fn action_code(action: AdminAction) -> u8 {
+    match action {
+        AdminAction::Unlock => 1,
+        AdminAction::Freeze => 2,
+        AdminAction::Review => 3,
+        AdminAction::Close => 4,
+    }
+}
+
+// Note records holding `text` as `field`
+fn notes(field: u8, text: &Option<String>) -> Vec<[u8; RECORD_LEN]> {
+    let text = text.as_deref().unwrap_or("").as_bytes();
+    text.chunks(NOTE_LEN)
+        .map(|chunk| {
+            let mut rec = [0u8; RECORD_LEN];
+            rec[0] = TAG_NOTE;
+            rec[1] = field;
+            rec[2] = chunk.len() as u8;
+            rec[3..3 + chunk.len()].copy_from_slice(chunk);
+            seal(&mut rec);
+            rec
+        })
+        .collect()
+}
+
+// The records of `tx`: one, unless it is an admin transaction with notes
+fn encode(tx: &Transaction) -> io::Result<Vec<[u8; RECORD_LEN]>> {
+    let mut rec = [0u8; RECORD_LEN];
+    rec[2..4].copy_from_slice(&tx.client().to_le_bytes());
+    rec[4..8].copy_from_slice(&tx.id().to_le_bytes());
+
+    let tag = match tx {
+        Transaction::Deposit { .. } => 1,
+        Transaction::Withdrawal { .. } => 2,
+        Transaction::Dispute { .. } => 3,
+        Transaction::Resolve { .. } => 4,
+        Transaction::Chargeback { .. } => 5,
+        Transaction::Authorize { .. } => 6,
+        Transaction::Capture { .. } => 7,
+        Transaction::Void { .. } => 8,
+        Transaction::Admin {
+            action,
+            actor,
+            reason,
+            ..
+        } => {
+            let mut notes = notes(NOTE_ACTOR, actor);
+            notes.extend(self::notes(NOTE_REASON, reason));
+            let count = u16::try_from(notes.len()).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "the actor and reason of {} tx {} are too long for a \
+                        binary log",
+                        tx.ty(),
+                        tx.id()
+                    ),
+                )
+            })?;
+            rec[0] = TAG_ADMIN;
+            rec[8] = action_code(*action);
+            rec[9..11].copy_from_slice(&count.to_le_bytes());
+            seal(&mut rec);
+            notes.insert(0, rec);
+            return Ok(notes);
+        }
+    };
+    let amount = tx.amount();
+    rec[0] = tag;
+    rec[1] = if amount.is_some() { FLAG_AMOUNT } else { 0 };
+    rec[8..16].copy_from_slice(&amount.unwrap_or(0.).to_bits().to_le_bytes());
+    seal(&mut rec);
+    Ok(vec![rec])
+}
+
+// A record on its own. Admin transactions still need their notes
+#[derive(Debug)]
+enum Record {
+    Tx(Transaction),
+    Admin(Transaction, u16),
+    Note(u8, Vec<u8>),
+}
+
+fn decode(idx: u64, rec: &[u8; RECORD_LEN]) -> Result<Record> {
+    let crc = u32::from_le_bytes(rec[16..].try_into().unwrap());
+    if crc != crc32(&rec[..16]) {
+        return Err(Error::Checksum(idx));
+    }
+    if rec[0] == TAG_NOTE {
+        let len = usize::from(rec[2]).min(NOTE_LEN);
+        return Ok(Record::Note(rec[1], rec[3..3 + len].to_vec()));
+    }
+
+    let client = u16::from_le_bytes(rec[2..4].try_into().unwrap());
+    let tx = u32::from_le_bytes(rec[4..8].try_into().unwrap());
+    let amount = if rec[1] & FLAG_AMOUNT != 0 {
+        let bits = u64::from_le_bytes(rec[8..16].try_into().unwrap());
+        Some(f64::from_bits(bits))
+    } else {
+        None
+    };
+
+    let tx = match (rec[0], amount) {
+        (TAG_ADMIN, _) => {
+            let action = match rec[8] {
+                1 => AdminAction::Unlock,
+                2 => AdminAction::Freeze,
+                3 => AdminAction::Review,
+                4 => AdminAction::Close,
+                _ => return Err(Error::BadAction(idx, rec[8])),
+            };
+            let notes = u16::from_le_bytes(rec[9..11].try_into().unwrap());
+            return Ok(Record::Admin(
+                Transaction::Admin {
+                    client,
+                    tx,
+                    action,
+                    actor: None,
+                    reason: None,
+                },
+                notes,
+            ));
+        }
+        (1, Some(amount)) => Ok(Transaction::Deposit {
+            client,
+            tx,
+            amount,
+            dispute: false,
+        }),
+        (2, Some(amount)) => Ok(Transaction::Withdrawal {
+            client,
+            tx,
+            amount,
+            dispute: false,
+        }),
+        (3, amount) => Ok(Transaction::Dispute { client, tx, amount }),
+        (4, amount) => Ok(Transaction::Resolve { client, tx, amount }),
+        (5, amount) => Ok(Transaction::Chargeback { client, tx, amount }),
+        (6, Some(amount)) => Ok(Transaction::Authorize { client, tx, amount }),
+        (7, amount) => Ok(Transaction::Capture { client, tx, amount }),
+        (8, _) => Ok(Transaction::Void { client, tx }),
+        (1, None) | (2, None) | (6, None) => Err(Error::MissingAmount(idx)),
+        (tag, _) => Err(Error::BadTag(idx, tag)),
+    };
+    tx.map(Record::Tx)
+}
+
+#[derive(Debug)]
+pub struct BinWriter<W: Write> {
+    wtr: W,
+}
+
+impl<W: Write> BinWriter<W> {
+    pub fn new(mut wtr: W) -> io::Result<Self> {
+        wtr.write_all(MAGIC)?;
+        wtr.write_all(&[VERSION])?;
+        Ok(Self { wtr })
+    }
+
+    pub fn write(&mut self, tx: &Transaction) -> io::Result<()> {
+        for rec in encode(tx)? {
+            self.wtr.write_all(&rec)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.wtr)
+    }
+}
+
+// Yields one Transaction per record, or per admin record and its notes. A
+// record that fails its checksum is reported and skipped; reading stops at
+// the first truncated record
+#[derive(Debug)]
+pub struct BinReader<R: Read> {
+    rdr: R,
+    idx: u64,
+    done: bool,
+}
+
+impl<R: Read> BinReader<R> {
+    pub fn new(mut rdr: R) -> Result<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        rdr.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::BadMagic,
+            _ => e.into(),
+        })?;
+
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(Error::UnsupportedVersion(header[MAGIC.len()]));
+        }
+
+        Ok(Self {
+            rdr,
+            idx: 0,
+            done: false,
+        })
+    }
+
+    // Fill `rec`, returning how many bytes were read before eof
+    fn fill(&mut self, rec: &mut [u8; RECORD_LEN]) -> io::Result<usize> {
+        let mut len = 0;
+        while len < RECORD_LEN {
+            match self.rdr.read(&mut rec[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(len)
+    }
+
+    // The next record, None at the end of the log
+    fn record(&mut self) -> Option<Result<[u8; RECORD_LEN]>> {
+        if self.done {
+            return None;
+        }
+
+        let idx = self.idx;
+        let mut rec = [0u8; RECORD_LEN];
+        let res = match self.fill(&mut rec) {
+            Ok(0) => {
+                self.done = true;
+                return None;
+            }
+            Ok(RECORD_LEN) => Ok(rec),
+            Ok(_) => {
+                self.done = true;
+                Err(Error::Truncated(idx))
+            }
+            Err(e) => {
+                self.done = true;
+                Err(e.into())
+            }
+        };
+
+        self.idx += 1;
+        Some(res)
+    }
+
+    // Read the `count` notes of the admin transaction `tx`. All of them are
+    // read even if one is bad, so the next record is a transaction again
+    fn notes(
+        &mut self,
+        mut tx: Transaction,
+        count: u16,
+    ) -> Result<Transaction> {
+        let (mut actor, mut reason) = (None, None);
+        let mut res = Ok(());
+        for _ in 0..count {
+            let idx = self.idx;
+            let rec = match self.record() {
+                Some(rec) => rec?,
+                None => return Err(Error::Truncated(idx)),
+            };
+            let (slot, bytes) = match decode(idx, &rec) {
+                Ok(Record::Note(NOTE_ACTOR, text)) => (&mut actor, text),
+                Ok(Record::Note(NOTE_REASON, text)) => (&mut reason, text),
+                Ok(_) => {
+                    res = res.and(Err(Error::BadNote(idx)));
+                    continue;
+                }
+                Err(e) => {
+                    res = res.and(Err(e));
+                    continue;
+                }
+            };
+            slot.get_or_insert_with(Vec::new).extend(bytes);
+        }
+        res?;
+
+        let text = |bytes: Option<Vec<u8>>| {
+            bytes
+                .map(String::from_utf8)
+                .transpose()
+                .map_err(|_| Error::BadNote(self.idx - 1))
+        };
+        if let Transaction::Admin {
+            actor: ref mut a,
+            reason: ref mut r,
+            ..
+        } = tx
+        {
+            *a = text(actor)?;
+            *r = text(reason)?;
+        }
+        Ok(tx)
+    }
+}
+
+impl<R: Read> Iterator for BinReader<R> {
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.idx;
+        let rec = match self.record()? {
+            Ok(rec) => rec,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(match decode(idx, &rec) {
+            Ok(Record::Tx(tx)) => Ok(tx),
+            Ok(Record::Admin(tx, count)) => self.notes(tx, count),
+            Ok(Record::Note(..)) => Err(Error::StrayNote(idx)),
+            Err(e) => Err(e),
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use crate::events::{Event, Subscriber};
+use serde::Serialize;
+use std::io;
+
+#[cfg(test)]
+mod tests {
+    use super::ChangeLog;
+    use crate::events::{Event, Subscriber};
+
+    #[test]
+    fn test_change_log() {
+        let balance = |tx, available, locked| Event::BalanceChanged {
+            client: 1,
+            tx,
+            available,
+            held: 0.,
+            total: available,
+            locked,
+        };
+        let mut log = ChangeLog::new(Vec::new());
+        log.notify(&Event::Deposited {
+            client: 1,
+            tx: 1,
+            amount: 2.,
+        });
+        log.notify(&balance(1, 2., false));
+        log.notify(&balance(4, 0.5, true));
+
+        let out = String::from_utf8(log.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "\
+seq,tx,client,available,held,total,locked
+1,1,1,2.0,0.0,2.0,false
+2,4,1,0.5,0.0,0.5,true
+"
+        );
+    }
+}
+
+// A client's balances after a transaction changed them. `seq` numbers the
+// changes of a run from 1, in the order they happened
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub seq: u64,
+    pub tx: u32,
+    pub client: u16,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+// Writes a csv row for every balance change. Writing stops at the first
+// error, which flush returns
+#[derive(Debug)]
+pub struct ChangeLog<W: io::Write> {
+    wtr: csv::Writer<W>,
+    seq: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> ChangeLog<W> {
+    pub fn new(wtr: W) -> Self {
+        Self {
+            wtr: csv::Writer::from_writer(wtr),
+            seq: 0,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        Subscriber::flush(&mut self)?;
+        self.wtr
+            .into_inner()
+            .map_err(|e| io::Error::new(e.error().kind(), e.to_string()))
+    }
+}
+
+impl<W: io::Write> Subscriber for ChangeLog<W> {
+    fn notify(&mut self, event: &Event) {
+        let change = match *event {
+            Event::BalanceChanged {
+                client,
+                tx,
+                available,
+                held,
+                total,
+                locked,
+            } => Change {
+                seq: self.seq + 1,
+                tx,
+                client,
+                available,
+                held,
+                total,
+                locked,
+            },
+            _ => return,
+        };
+        if self.error.is_some() {
+            return;
+        }
+        match self.wtr.serialize(&change) {
+            Ok(()) => self.seq = change.seq,
+            Err(e) => self.error = Some(e.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.wtr.flush(),
+        }
+    }
+}
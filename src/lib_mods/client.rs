@@ -1,9 +1,10 @@
-use std::result;
-use serde::Serialize;
+use crate::EPSILON;
+use std::{convert::TryFrom, fmt, result};
+use serde::{Deserialize, Serialize, Serializer};
 
 #[cfg(test)]
 mod tests {
-    use super::{Client, Error};
+    use super::{Client, Error, Hold, HoldReason, Operation, Status};
     use std::io;
 
     #[test]
@@ -12,13 +13,65 @@ mod tests {
         wtr.serialize(Client {
             _client: 1,
             _available: 25.1234,
-            _held: 5.,
+            _holds: vec![Hold {
+                id: 1,
+                tx: Some(2),
+                reason: HoldReason::Dispute,
+                amount: 5.,
+            }],
+            _next_hold: 2,
             _total: 30.1234,
             _locked: false,
+            _status: Status::Active,
+            _debt: 0.,
         })
         .unwrap();
     }
 
+    #[test]
+    fn test_client_de() {
+        let data = "\
+client,available,held,total,locked,status,debt
+1,1.5,2.0,3.5,false,frozen,
+2,0.0,1.0,0.25,true,locked,0.75
+3,1.0,0.0,1.0,false,active,0
+";
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let clients: Vec<Client> =
+            rdr.deserialize().collect::<Result<_, _>>().unwrap();
+        assert_eq!(clients[0].held(), 2.);
+        assert_eq!(clients[0].status(), Status::Frozen);
+        assert_eq!(clients[1].debt(), 0.75);
+        assert!(clients[1].is_locked());
+        assert_eq!(clients[2].total(), 1.);
+
+        let data = "client,available,held,total,locked\n\
+            1,1.0,0.0,1.0,false\n\
+            2,0.0,1.0,1.0,true\n";
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let clients: Vec<Client> =
+            rdr.deserialize().collect::<Result<_, _>>().unwrap();
+        assert_eq!(clients[0].status(), Status::Active);
+        assert_eq!(clients[0].total(), 1.);
+        assert_eq!(clients[1].status(), Status::Locked);
+
+        for bad in [
+            "client,available,held,total,locked,status\n\
+            1,1.0,0.0,2.0,false,active\n",
+            "client,available,held,total,locked,status\n\
+            1,1.0,0.0,0.5,false,active\n",
+            "client,available,held,total,locked,status,debt\n\
+            1,1.0,0.0,0.5,false,active,0.25\n",
+            "client,available,held,total,locked,status\n\
+            1,1.0,0.0,1.0,false,closed\n",
+        ]
+        .iter()
+        {
+            let mut rdr = csv::Reader::from_reader(bad.as_bytes());
+            assert!(rdr.deserialize::<Client>().next().unwrap().is_err());
+        }
+    }
+
     #[test]
     fn test_client_new() {
         let _ = Client::new(1, 3450.123);
@@ -297,6 +350,157 @@ mod tests {
         client.unlock();
         assert!(!client.is_locked());
     }
+
+    #[test]
+    fn test_client_force_hold_release_debt() {
+        let mut client = Client::new(1, 2.);
+        let shortfall = client.force_hold(7, 5.).unwrap();
+        assert!(float_cmp::approx_eq!(f64, shortfall, 3., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.available(), 0., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.held(), 5., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.total(), 2., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.debt(), 3., ulps = 1));
+
+        assert!(client.release(7, 6.).is_err());
+        assert!(client.release(8, 1.).is_err());
+        client.release(7, 5.).unwrap();
+        assert!(client.holds().is_empty());
+        assert!(float_cmp::approx_eq!(f64, client.held(), 0., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.total(), -3., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.debt(), 3., ulps = 1));
+
+        // a locked client can still settle its disputes, a closed one not
+        client.lock();
+        assert!(client.force_hold(7, 1.).is_err());
+        client.release(7, 0.).unwrap();
+        client.repay(0.).unwrap();
+        client.set_status(Status::Closed);
+        assert!(client.release(7, 0.).is_err());
+    }
+
+    #[test]
+    fn test_client_status() {
+        let mut client = Client::new(1, 2.);
+        client.set_status(Status::Frozen);
+        assert!(!client.is_locked());
+        client.add(1.).unwrap();
+
+        client.set_status(Status::Closed);
+        assert!(client.is_locked());
+        match client.add(1.).unwrap_err() {
+            Error::Closed(1) => (),
+            e => panic!("expected Error::Closed, got {:?}", e),
+        }
+
+        client.lock();
+        assert_eq!(client.status(), Status::Locked);
+        match client.rm(1.).unwrap_err() {
+            Error::Locked(1) => (),
+            e => panic!("expected Error::Locked, got {:?}", e),
+        }
+        client.unlock();
+        assert_eq!(client.status(), Status::Active);
+        client.rm(1.).unwrap();
+    }
+
+    #[test]
+    fn test_status_permits() {
+        use Operation::*;
+        let table = [
+            (Status::Active, [true, true, true, true]),
+            (Status::Frozen, [true, false, true, true]),
+            (Status::UnderReview, [false, false, true, true]),
+            (Status::Locked, [false, false, false, true]),
+            (Status::Closed, [false, false, false, false]),
+        ];
+        for (status, permits) in table.iter() {
+            let ops = [Deposit, Withdraw, Dispute, Settle];
+            for (op, permit) in ops.iter().zip(permits.iter()) {
+                assert_eq!(status.permits(*op), *permit, "{} {:?}", status, op);
+            }
+        }
+        assert_eq!(Status::UnderReview.to_string(), "under-review");
+    }
+
+    #[test]
+    fn test_client_holds() {
+        let mut client = Client::new(1, 10.);
+        client.hold_for(2, HoldReason::Dispute, 1.).unwrap();
+        client.hold_for(3, HoldReason::Authorization, 2.).unwrap();
+        client.hold_for(2, HoldReason::Dispute, 0.5).unwrap();
+        client.hold(3.).unwrap();
+        assert!(float_cmp::approx_eq!(f64, client.held(), 6.5, ulps = 1));
+        assert_eq!(
+            client.holds(),
+            &[
+                Hold {
+                    id: 1,
+                    tx: Some(2),
+                    reason: HoldReason::Dispute,
+                    amount: 1.5,
+                },
+                Hold {
+                    id: 2,
+                    tx: Some(3),
+                    reason: HoldReason::Authorization,
+                    amount: 2.,
+                },
+                Hold {
+                    id: 3,
+                    tx: None,
+                    reason: HoldReason::Reserve,
+                    amount: 3.,
+                },
+            ]
+        );
+
+        // holds are only released by what they were made for
+        match client.unhold_for(3, HoldReason::Dispute, 1.).unwrap_err() {
+            Error::InsufficientFunds(1, held) => assert_eq!(held, 0.),
+            e => panic!("expected Error::InsufficientFunds, got {:?}", e),
+        }
+        assert!(client.unhold_for(2, HoldReason::Dispute, 2.).is_err());
+        client.unhold_for(2, HoldReason::Dispute, 1.5).unwrap();
+        client.unhold_for(3, HoldReason::Authorization, 0.5).unwrap();
+        let ids: Vec<u32> = client.holds().iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert!(float_cmp::approx_eq!(f64, client.held(), 4.5, ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.available(), 5.5, ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.total(), 10., ulps = 1));
+
+        let client = Client::from_parts(2, 1., 2., false);
+        assert_eq!(client.holds()[0].reason, HoldReason::Reserve);
+        let client = client.with_holds(vec![Hold {
+            id: 4,
+            tx: Some(1),
+            reason: HoldReason::Dispute,
+            amount: 3.,
+        }]);
+        assert!(float_cmp::approx_eq!(f64, client.held(), 3., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.total(), 4., ulps = 1));
+    }
+
+    #[test]
+    fn test_client_repay() {
+        let mut client = Client::new(1, 0.).with_debt(3.);
+        assert!(float_cmp::approx_eq!(f64, client.total(), -3., ulps = 1));
+        let paid = client.repay(1.).unwrap();
+        assert!(float_cmp::approx_eq!(f64, paid, 0., ulps = 1));
+
+        client.add(2.).unwrap();
+        let paid = client.repay(5.).unwrap();
+        assert!(float_cmp::approx_eq!(f64, paid, 2., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.available(), 0., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.debt(), 1., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.total(), -1., ulps = 1));
+
+        client.add(4.).unwrap();
+        let paid = client.repay(5.).unwrap();
+        assert!(float_cmp::approx_eq!(f64, paid, 1., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.available(), 3., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.debt(), 0., ulps = 1));
+        assert!(float_cmp::approx_eq!(f64, client.total(), 3., ulps = 1));
+    }
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -306,22 +510,189 @@ pub enum Error {
     #[error("client {0} is locked")]
     Locked(u16),
 
+    #[error("client {0} is closed")]
+    Closed(u16),
+
     #[error("client {0} has insufficient funds of {1}")]
     InsufficientFunds(u16, f64),
 }
 
-#[derive(Debug, Serialize)]
+// What an account may still be used for, see Status::permits. Client
+// itself only refuses changes to locked and closed accounts; the engine
+// checks the rest, since it knows a withdrawal from the reversal of a
+// disputed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Active,
+    // no withdrawals
+    Frozen,
+    // no money moves in or out while the account is looked into. Disputes
+    // can still be opened and settled
+    UnderReview,
+    // after a chargeback. Nothing until unlocked but settling the disputes
+    // that are still open
+    Locked,
+    // nothing, for good
+    Closed,
+}
+
+// The kinds of transactions an account status may refuse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Deposit,
+    Withdraw,
+    Dispute,
+    // resolve or chargeback
+    Settle,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Deposit => "deposit",
+            Operation::Withdraw => "withdrawal",
+            Operation::Dispute => "dispute",
+            Operation::Settle => "resolve or chargeback",
+        }
+    }
+}
+
+impl Status {
+    pub fn permits(&self, op: Operation) -> bool {
+        match (self, op) {
+            (Status::Active, _) => true,
+            (Status::Frozen, op) => op != Operation::Withdraw,
+            (Status::UnderReview, op) => {
+                op == Operation::Dispute || op == Operation::Settle
+            }
+            (Status::Locked, op) => op == Operation::Settle,
+            (Status::Closed, _) => false,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Active => "active",
+            Status::Frozen => "frozen",
+            Status::UnderReview => "under-review",
+            Status::Locked => "locked",
+            Status::Closed => "closed",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// What funds are held for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HoldReason {
+    Dispute,
+    Authorization,
+    // held without a transaction to release it, e.g. balances loaded from
+    // a file that only has the held total
+    Reserve,
+}
+
+// Funds held for one transaction. Holding more for the same transaction
+// and reason adds to the existing hold
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hold {
+    // unique per client
+    pub id: u32,
+    pub tx: Option<u32>,
+    pub reason: HoldReason,
+    pub amount: f64,
+}
+
+fn serialize_held<S: Serializer>(
+    holds: &[Hold],
+    s: S,
+) -> result::Result<S::Ok, S::Error> {
+    s.serialize_f64(sum(holds))
+}
+
+// summing an empty iterator of floats gives -0
+fn sum(holds: &[Hold]) -> f64 {
+    holds.iter().fold(0., |sum, hold| sum + hold.amount)
+}
+
+// A client as written in the output, with optional `status` and `debt`
+// columns. Debt is not part of the output, so a total below available +
+// held is only taken as debt when the debt column says so. Without a
+// status column the status follows `locked`
+#[derive(Debug, Deserialize)]
+struct ClientRecord {
+    client: u16,
+    available: f64,
+    held: f64,
+    total: f64,
+    locked: bool,
+    #[serde(default)]
+    status: Option<Status>,
+    #[serde(default)]
+    debt: Option<f64>,
+}
+
+impl TryFrom<ClientRecord> for Client {
+    type Error = String;
+
+    fn try_from(rec: ClientRecord) -> result::Result<Self, Self::Error> {
+        let debt = rec.debt.unwrap_or(0.);
+        let expected = rec.available + rec.held - debt;
+        if (rec.total - expected).abs() > EPSILON {
+            return Err(format!(
+                "client {}: total {} is not available + held - debt ({})",
+                rec.client, rec.total, expected
+            ));
+        }
+        let status = match rec.status {
+            Some(status) => status,
+            None if rec.locked => Status::Locked,
+            None => Status::Active,
+        };
+        let client =
+            Client::from_parts(rec.client, rec.available, rec.held, rec.locked)
+                .with_debt(debt)
+                .with_status(status);
+        if client.is_locked() != rec.locked {
+            return Err(format!(
+                "client {}: locked is {} for a {} account",
+                rec.client, rec.locked, status
+            ));
+        }
+        Ok(client)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "ClientRecord")]
 pub struct Client {
     #[serde(rename = "client")]
     _client: u16,
     #[serde(rename = "available")]
     _available: f64,
-    #[serde(rename = "held")]
-    _held: f64,
+    #[serde(rename = "held", serialize_with = "serialize_held")]
+    _holds: Vec<Hold>,
+    #[serde(skip)]
+    _next_hold: u32,
     #[serde(rename = "total")]
     _total: f64,
+    // kept in step with _status: set for the statuses that refuse
+    // everything
     #[serde(rename = "locked")]
     _locked: bool,
+    #[serde(rename = "status")]
+    _status: Status,
+    // owed by the client, see Client::debt. Not part of the output, where it
+    // shows as total being less than available + held
+    #[serde(skip)]
+    _debt: f64,
 }
 
 impl Client {
@@ -329,31 +700,88 @@ impl Client {
         Self {
             _client: id,
             _available: available,
-            _held: 0.,
+            _holds: Vec::new(),
+            _next_hold: 1,
             _total: available,
             _locked: false,
+            _status: Status::Active,
+            _debt: 0.,
         }
     }
 
+    pub fn from_parts(
+        id: u16,
+        available: f64,
+        held: f64,
+        locked: bool,
+    ) -> Self {
+        let mut client = Self {
+            _client: id,
+            _available: available,
+            _holds: Vec::new(),
+            _next_hold: 1,
+            _total: available + held,
+            _locked: locked,
+            _status: if locked { Status::Locked } else { Status::Active },
+            _debt: 0.,
+        };
+        client.put_hold(None, HoldReason::Reserve, held);
+        client
+    }
+
+    // Replace the holds, e.g. ones restored from a snapshot. Available
+    // stays as it is
+    pub fn with_holds(mut self, holds: Vec<Hold>) -> Self {
+        self._total -= self.held();
+        self._holds = holds;
+        self._total += self.held();
+        self._next_hold =
+            self._holds.iter().map(|hold| hold.id + 1).max().unwrap_or(1);
+        self
+    }
+
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.set_status(status);
+        self
+    }
+
+    pub fn with_debt(mut self, debt: f64) -> Self {
+        self._debt += debt;
+        self._total -= debt;
+        self
+    }
+
     pub fn id(&self) -> u16 {
-        return self._client;
+        self._client
     }
 
     pub fn available(&self) -> f64 {
-        return self._available;
+        self._available
     }
 
     pub fn held(&self) -> f64 {
-        return self._held;
+        sum(&self._holds)
+    }
+
+    // Active holds, oldest first
+    pub fn holds(&self) -> &[Hold] {
+        &self._holds
     }
 
     pub fn total(&self) -> f64 {
-        return self._total;
+        self._total
+    }
+
+    // What the client owes after funds were taken back that it no longer
+    // had. Counted against total but never against available, which stays
+    // at zero or above
+    pub fn debt(&self) -> f64 {
+        self._debt
     }
 
     pub fn add(&mut self, amt: f64) -> Result<()> {
         if self.is_locked() {
-            return Err(Error::Locked(self.id()));
+            return Err(self.refusal());
         }
         self._available += amt;
         self._total += amt;
@@ -362,7 +790,7 @@ impl Client {
 
     pub fn rm(&mut self, amt: f64) -> Result<()> {
         if self.is_locked() {
-            return Err(Error::Locked(self.id()));
+            return Err(self.refusal());
         } else if amt > self.available() {
             return Err(Error::InsufficientFunds(self.id(), self.available()));
         }
@@ -371,38 +799,171 @@ impl Client {
         Ok(())
     }
 
+    // Hold funds as a reserve, not tied to any transaction
     pub fn hold(&mut self, amt: f64) -> Result<()> {
+        self.hold_as(None, HoldReason::Reserve, amt)
+    }
+
+    pub fn hold_for(
+        &mut self,
+        tx: u32,
+        reason: HoldReason,
+        amt: f64,
+    ) -> Result<()> {
+        self.hold_as(Some(tx), reason, amt)
+    }
+
+    fn hold_as(
+        &mut self,
+        tx: Option<u32>,
+        reason: HoldReason,
+        amt: f64,
+    ) -> Result<()> {
         if self.is_locked() {
-            return Err(Error::Locked(self.id()));
+            return Err(self.refusal());
         } else if amt > self.available() {
             return Err(Error::InsufficientFunds(self.id(), self.available()));
+        } else if amt <= 0. || amt.is_nan() {
+            // nothing to hold, and put_hold would not record it
+            return Ok(());
         }
-        self._held += amt;
+        self.put_hold(tx, reason, amt);
         self._available -= amt;
         Ok(())
     }
 
-    pub fn unhold(&mut self, amt: f64) -> Result<()> {
+    // Like hold_for a dispute, but holds `amt` in full when available does
+    // not cover it. The shortfall is added to the debt and returned
+    pub fn force_hold(&mut self, tx: u32, amt: f64) -> Result<f64> {
         if self.is_locked() {
-            return Err(Error::Locked(self.id()));
-        } else if amt > self.held() {
-            return Err(Error::InsufficientFunds(self.id(), self.held()));
+            return Err(self.refusal());
         }
+        let shortfall = (amt - self.available()).max(0.);
+        self.put_hold(Some(tx), HoldReason::Dispute, amt);
+        self._available -= amt - shortfall;
+        self._debt += shortfall;
+        Ok(shortfall)
+    }
 
-        self._held -= amt;
-        self._available += amt;
+    // Pay off up to `amt` of the debt from available funds, returning how
+    // much was paid. Locked clients still repay what a settled dispute held
+    // on credit
+    pub fn repay(&mut self, amt: f64) -> Result<f64> {
+        let paid = amt.min(self.debt()).min(self.available()).max(0.);
+        self._available -= paid;
+        self._debt -= paid;
+        Ok(paid)
+    }
+
+    // Take funds held for a dispute out of the account, e.g. on a
+    // chargeback
+    pub fn release(&mut self, tx: u32, amt: f64) -> Result<()> {
+        let taken = self.take_hold(Some(tx), HoldReason::Dispute, amt)?;
+        self._total -= taken;
+        Ok(())
+    }
+
+    // Make reserved funds available again
+    pub fn unhold(&mut self, amt: f64) -> Result<()> {
+        let taken = self.take_hold(None, HoldReason::Reserve, amt)?;
+        self._available += taken;
         Ok(())
     }
 
+    pub fn unhold_for(
+        &mut self,
+        tx: u32,
+        reason: HoldReason,
+        amt: f64,
+    ) -> Result<()> {
+        let taken = self.take_hold(Some(tx), reason, amt)?;
+        self._available += taken;
+        Ok(())
+    }
+
+    fn put_hold(&mut self, tx: Option<u32>, reason: HoldReason, amt: f64) {
+        if amt <= 0. {
+            return;
+        }
+        match self
+            ._holds
+            .iter_mut()
+            .find(|hold| hold.tx == tx && hold.reason == reason)
+        {
+            Some(hold) => hold.amount += amt,
+            None => {
+                self._holds.push(Hold {
+                    id: self._next_hold,
+                    tx,
+                    reason,
+                    amount: amt,
+                });
+                self._next_hold += 1;
+            }
+        }
+    }
+
+    // Take `amt` off the hold for `tx` and `reason`, returning how much was
+    // taken. A hold left with next to nothing is taken in full. Locked
+    // clients keep their holds, except that disputes can still be settled
+    fn take_hold(
+        &mut self,
+        tx: Option<u32>,
+        reason: HoldReason,
+        amt: f64,
+    ) -> Result<f64> {
+        let settling = reason == HoldReason::Dispute
+            && self.status().permits(Operation::Settle);
+        if self.is_locked() && !settling {
+            return Err(self.refusal());
+        }
+        let idx = self
+            ._holds
+            .iter()
+            .position(|hold| hold.tx == tx && hold.reason == reason);
+        let held = idx.map_or(0., |idx| self._holds[idx].amount);
+        if amt > held + EPSILON {
+            return Err(Error::InsufficientFunds(self.id(), held));
+        }
+        match idx {
+            Some(idx) if held - amt <= EPSILON => {
+                self._holds.remove(idx);
+                Ok(held)
+            }
+            Some(idx) => {
+                self._holds[idx].amount -= amt;
+                Ok(amt)
+            }
+            None => Ok(0.),
+        }
+    }
+
     pub fn lock(&mut self) {
-        self._locked = true;
+        self.set_status(Status::Locked);
     }
 
     pub fn unlock(&mut self) {
-        self._locked = false;
+        self.set_status(Status::Active);
     }
 
     pub fn is_locked(&self) -> bool {
-        return self._locked;
+        self._locked
+    }
+
+    pub fn status(&self) -> Status {
+        self._status
+    }
+
+    pub fn set_status(&mut self, status: Status) {
+        self._status = status;
+        self._locked = matches!(status, Status::Locked | Status::Closed);
+    }
+
+    // Why a locked client refuses changes
+    pub(crate) fn refusal(&self) -> Error {
+        match self.status() {
+            Status::Closed => Error::Closed(self.id()),
+            _ => Error::Locked(self.id()),
+        }
     }
 }
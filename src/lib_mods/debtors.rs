@@ -0,0 +1,67 @@
+use crate::store::ClientStore;
+use serde::Serialize;
+
+#[cfg(test)]
+mod tests {
+    use super::{report, Debtor};
+    use crate::{client::Client, store::ClientStore, store::VecStore};
+
+    #[test]
+    fn test_debtors_report() {
+        let mut clients = VecStore::new();
+        clients.insert(Client::new(3, 1.).with_debt(2.));
+        clients.insert(Client::new(1, 5.));
+        clients.insert(Client::from_parts(2, 0., 4., true).with_debt(0.5));
+
+        assert_eq!(
+            report(&clients),
+            vec![
+                Debtor {
+                    client: 2,
+                    debt: 0.5,
+                    available: 0.,
+                    held: 4.,
+                    total: 3.5,
+                    locked: true,
+                },
+                Debtor {
+                    client: 3,
+                    debt: 2.,
+                    available: 1.,
+                    held: 0.,
+                    total: -1.,
+                    locked: false,
+                },
+            ]
+        );
+    }
+}
+
+// A client that owes money, see Client::debt
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Debtor {
+    pub client: u16,
+    pub debt: f64,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+// Every client in `clients` with debt, by client id
+pub fn report<S: ClientStore>(clients: &S) -> Vec<Debtor> {
+    let mut debtors: Vec<Debtor> = clients
+        .iter()
+        .filter(|client| client.debt() > 0.)
+        .map(|client| Debtor {
+            client: client.id(),
+            debt: client.debt(),
+            available: client.available(),
+            held: client.held(),
+            total: client.total(),
+            locked: client.is_locked(),
+        })
+        .collect();
+    debtors.sort_by_key(|debtor| debtor.client);
+    debtors
+}
@@ -0,0 +1,375 @@
+use crate::{
+    reconcile::Balance,
+    snapshot::{ClientState, Snapshot, TxState},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, io,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, ChangeKind, FieldChange, State};
+    use crate::snapshot::Snapshot;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_outputs() {
+        let before = State::from_output(
+            "client,available,held,total,locked,status
+1,1.0,0.0,1.0,false,active
+2,2.0,0.0,2.0,false,active
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        // older outputs have no status, which is not a change
+        let after = State::from_output(
+            "client,available,held,total,locked
+1,1.0,0.0,1.0,false
+3,3.0,0.0,3.0,false
+"
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let changes = diff(&before, &after, false);
+        assert!(changes.transactions.is_empty());
+        let clients: Vec<_> = changes
+            .clients
+            .iter()
+            .map(|change| (change.id, change.kind, change.fields.len()))
+            .collect();
+        assert_eq!(
+            clients,
+            vec![(2, ChangeKind::Removed, 5), (3, ChangeKind::Added, 4)]
+        );
+        assert_eq!(
+            changes.to_string(),
+            "\
+client 2 removed
+  available: 2.0 -> -
+  held: 0.0 -> -
+  locked: false -> -
+  status: active -> -
+  total: 2.0 -> -
+client 3 added
+  available: - -> 3.0
+  held: - -> 0.0
+  locked: - -> false
+  total: - -> 3.0
+"
+        );
+
+        let after = State::from_output(
+            "client,available,held,total,locked,status
+1,1.0,0.0,1.0,true,locked
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let changes = diff(&before, &after, false);
+        assert_eq!(changes.clients[0].id, 1);
+        assert_eq!(
+            changes.clients[0].fields[1],
+            FieldChange {
+                field: String::from("status"),
+                before: json!("active"),
+                after: json!("locked"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let before: Snapshot = serde_json::from_str(
+            r#"{
+            "clients": [
+                {"client": 1, "available": 1, "held": 2, "locked": false,
+                 "status": "active", "debt": 0, "holds": [
+                    {"id": 1, "tx": 1, "reason": "dispute", "amount": 2}
+                 ]}
+            ],
+            "transactions": [
+                {"type": "deposit", "client": 1, "tx": 1, "amount": 3,
+                 "disputed": true, "disputed_amount": 2, "held_amount": 2,
+                 "credit": 0, "charged_back": 0}
+            ],
+            "authorizations": []
+        }"#,
+        )
+        .unwrap();
+        let after: Snapshot = serde_json::from_str(
+            r#"{
+            "clients": [
+                {"client": 1, "available": 1, "held": 0, "locked": true,
+                 "status": "locked", "debt": 0.5, "holds": []}
+            ],
+            "transactions": [
+                {"type": "deposit", "client": 1, "tx": 1, "amount": 3,
+                 "disputed": false, "disputed_amount": 0, "held_amount": 0,
+                 "credit": 0, "charged_back": 2}
+            ],
+            "authorizations": []
+        }"#,
+        )
+        .unwrap();
+
+        let changes = diff(
+            &State::from_snapshot(&before),
+            &State::from_snapshot(&after),
+            true,
+        );
+        let fields: Vec<&str> = changes.clients[0]
+            .fields
+            .iter()
+            .map(|change| change.field.as_str())
+            .collect();
+        assert_eq!(fields, vec!["held", "locked", "status", "total"]);
+        assert_eq!(changes.clients[0].fields[3].after, json!(0.5));
+        let fields: Vec<&str> = changes.transactions[0]
+            .fields
+            .iter()
+            .map(|change| change.field.as_str())
+            .collect();
+        assert_eq!(
+            fields,
+            vec!["charged_back", "disputed", "disputed_amount", "held_amount"]
+        );
+
+        assert!(diff(
+            &State::from_snapshot(&before),
+            &State::from_snapshot(&after),
+            false
+        )
+        .transactions
+        .is_empty());
+    }
+}
+
+// Snapshots have the fields of the output except for total, which is
+// worked out from their debt
+impl From<&ClientState> for Balance {
+    fn from(state: &ClientState) -> Self {
+        Self {
+            client: state.client,
+            available: state.available,
+            held: state.held,
+            total: state.available + state.held - state.debt,
+            locked: state.locked,
+            status: Some(state.status),
+        }
+    }
+}
+
+// One side of a diff: the clients of an output or a snapshot, and the
+// transactions of a snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub clients: Vec<Balance>,
+    // outputs have no transactions
+    pub transactions: Option<Vec<TxState>>,
+}
+
+impl State {
+    pub fn from_output<R: io::Read>(rdr: R) -> csv::Result<Self> {
+        let clients = csv::Reader::from_reader(rdr)
+            .deserialize()
+            .collect::<csv::Result<_>>()?;
+        Ok(Self {
+            clients,
+            transactions: None,
+        })
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        Self {
+            clients: snapshot.clients.iter().map(Balance::from).collect(),
+            transactions: Some(snapshot.transactions.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+// A field that differs. Fields a side does not have are null
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+// A client or transaction that differs. Added and removed ones list every
+// field they have
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub id: u32,
+    pub kind: ChangeKind,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Diff {
+    // by id
+    pub clients: Vec<Change>,
+    pub transactions: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty() && self.transactions.is_empty()
+    }
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("-"),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changes = self
+            .clients
+            .iter()
+            .map(|change| ("client", change))
+            .chain(self.transactions.iter().map(|change| ("tx", change)));
+        for (what, change) in changes {
+            writeln!(f, "{} {} {}", what, change.id, change.kind.name())?;
+            for field in &change.fields {
+                writeln!(
+                    f,
+                    "  {}: {} -> {}",
+                    field.field,
+                    show(&field.before),
+                    show(&field.after)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The fields of `item` but its id, by name. Serializing the structs the
+// tool reads cannot fail
+fn fields<T: Serialize>(item: &T, id: &str) -> BTreeMap<String, Value> {
+    match serde_json::to_value(item).unwrap() {
+        Value::Object(mut map) => {
+            map.remove(id);
+            map.into_iter().collect()
+        }
+        _ => unreachable!(),
+    }
+}
+
+// `optional` fields are missing from some formats, so a side without one
+// says nothing about it
+fn compare<T: Serialize>(
+    id_field: &str,
+    optional: &[&str],
+    before: &BTreeMap<u32, &T>,
+    after: &BTreeMap<u32, &T>,
+) -> Vec<Change> {
+    let ids: BTreeSet<u32> =
+        before.keys().chain(after.keys()).copied().collect();
+    let mut changes = Vec::new();
+    for id in ids {
+        let (kind, mut before, mut after) =
+            match (before.get(&id), after.get(&id)) {
+                (Some(before), Some(after)) => (
+                    ChangeKind::Changed,
+                    fields(*before, id_field),
+                    fields(*after, id_field),
+                ),
+                (Some(before), None) => (
+                    ChangeKind::Removed,
+                    fields(*before, id_field),
+                    BTreeMap::new(),
+                ),
+                (None, Some(after)) => (
+                    ChangeKind::Added,
+                    BTreeMap::new(),
+                    fields(*after, id_field),
+                ),
+                (None, None) => unreachable!(),
+            };
+        if kind == ChangeKind::Changed {
+            for name in optional {
+                let missing = |fields: &BTreeMap<String, Value>| {
+                    fields.get(*name).map_or(true, Value::is_null)
+                };
+                if missing(&before) || missing(&after) {
+                    before.remove(*name);
+                    after.remove(*name);
+                }
+            }
+        }
+
+        let names: BTreeSet<&String> =
+            before.keys().chain(after.keys()).collect();
+        let fields: Vec<FieldChange> = names
+            .into_iter()
+            .map(|name| FieldChange {
+                field: name.clone(),
+                before: before.get(name).cloned().unwrap_or(Value::Null),
+                after: after.get(name).cloned().unwrap_or(Value::Null),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        if !fields.is_empty() {
+            changes.push(Change { id, kind, fields });
+        }
+    }
+    changes
+}
+
+fn by_client(state: &State) -> BTreeMap<u32, &Balance> {
+    state
+        .clients
+        .iter()
+        .map(|client| (client.client as u32, client))
+        .collect()
+}
+
+fn by_tx(txs: &[TxState]) -> BTreeMap<u32, &TxState> {
+    txs.iter().map(|state| (state.tx.tx, state)).collect()
+}
+
+// What changed from `before` to `after`. Transactions are only compared
+// with `disputes` and when both sides have them
+pub fn diff(before: &State, after: &State, disputes: bool) -> Diff {
+    let clients =
+        compare("client", &["status"], &by_client(before), &by_client(after));
+
+    let transactions = match (&before.transactions, &after.transactions) {
+        (Some(before), Some(after)) if disputes => {
+            compare("tx", &[], &by_tx(before), &by_tx(after))
+        }
+        _ => Vec::new(),
+    };
+    Diff {
+        clients,
+        transactions,
+    }
+}
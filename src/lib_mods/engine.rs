@@ -0,0 +1,1980 @@
+use crate::{
+    client::{self, Client, HoldReason, Operation, Status},
+    events::{Event, Subscriber, Subscribers},
+    store::ClientStore,
+    transaction::{AdminAction, Transaction},
+    EPSILON,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    io, result,
+    str::FromStr,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DebtRecovery, DisputeShortfall, DisputeState, Engine, Error, Policy,
+        Result, WithdrawalDisputes,
+    };
+    use crate::{
+        client::{self, Client, HoldReason, Status},
+        events::Event,
+        store::{ClientStore, VecStore},
+        transaction::{AdminAction, Transaction},
+    };
+    use float_cmp::approx_eq;
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    fn deposit(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::Deposit {
+            client,
+            tx,
+            amount,
+            dispute: false,
+        }
+    }
+
+    #[test]
+    fn test_engine_duplicate_tx_is_not_applied() {
+        let mut engine = Engine::new(HashMap::<u16, Client>::new());
+        engine.apply(deposit(1, 1, 2.)).unwrap();
+        match engine.apply(deposit(1, 1, 3.)).unwrap_err() {
+            Error::DuplicateTx(1) => (),
+            e => panic!("expected Error::DuplicateTx, got {:?}", e),
+        }
+
+        let client = engine.clients().get(&1).unwrap();
+        assert!(approx_eq!(f64, client.available(), 2., ulps = 1));
+    }
+
+    #[test]
+    fn test_engine_dispute_chargeback() {
+        let mut engine = Engine::new(VecStore::new());
+        engine.apply(deposit(1, 1, 2.)).unwrap();
+        engine.apply(deposit(1, 2, 3.)).unwrap();
+        engine
+            .apply(Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+
+        let client = engine.client(1).unwrap();
+        assert!(approx_eq!(f64, client.available(), 3., ulps = 1));
+        assert!(approx_eq!(f64, client.held(), 0., ulps = 1));
+        assert!(client.is_locked());
+    }
+
+    #[test]
+    fn test_engine_dispute_foreign_tx() {
+        let mut engine = Engine::new(VecStore::new());
+        engine.apply(deposit(1, 1, 2.)).unwrap();
+        engine.apply(deposit(2, 2, 2.)).unwrap();
+        match engine
+            .apply(Transaction::Dispute {
+                client: 2,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap_err()
+        {
+            Error::WrongClient { .. } => (),
+            e => panic!("expected Error::WrongClient, got {:?}", e),
+        }
+    }
+
+    fn dispute(client: u16, tx: u32, amount: Option<f64>) -> Transaction {
+        Transaction::Dispute { client, tx, amount }
+    }
+
+    #[test]
+    fn test_engine_partial_disputes() {
+        let mut engine = Engine::new(HashMap::<u16, Client>::new());
+        let balances = |engine: &Engine<HashMap<u16, Client>>| {
+            let client = engine.client(1).unwrap();
+            (client.available(), client.held(), client.total())
+        };
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+
+        engine.apply(dispute(1, 1, Some(4.))).unwrap();
+        engine.apply(dispute(1, 1, Some(5.))).unwrap();
+        assert_eq!(balances(&engine), (1., 9., 10.));
+        match engine.apply(dispute(1, 1, Some(2.))).unwrap_err() {
+            Error::ExceedsDisputable { remaining, .. } => {
+                assert!(approx_eq!(f64, remaining, 1., ulps = 1))
+            }
+            e => panic!("expected Error::ExceedsDisputable, got {:?}", e),
+        }
+
+        engine
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 1,
+                amount: Some(3.),
+            })
+            .unwrap();
+        assert_eq!(balances(&engine), (4., 6., 10.));
+        match engine
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 1,
+                amount: Some(7.),
+            })
+            .unwrap_err()
+        {
+            Error::ExceedsDisputed { .. } => (),
+            e => panic!("expected Error::ExceedsDisputed, got {:?}", e),
+        }
+
+        // without an amount a dispute takes whatever is left
+        engine.apply(dispute(1, 1, None)).unwrap();
+        assert_eq!(balances(&engine), (0., 10., 10.));
+        match engine.apply(dispute(1, 1, None)).unwrap_err() {
+            Error::AlreadyDisputed(1) => (),
+            e => panic!("expected Error::AlreadyDisputed, got {:?}", e),
+        }
+        match engine.apply(dispute(1, 1, Some(-1.))).unwrap_err() {
+            Error::InvalidAmount { .. } => (),
+            e => panic!("expected Error::InvalidAmount, got {:?}", e),
+        }
+
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: Some(2.),
+            })
+            .unwrap();
+        assert_eq!(balances(&engine), (0., 8., 8.));
+        // the first chargeback locks the account, but the rest of the
+        // dispute can still be settled
+        assert!(engine.client(1).unwrap().is_locked());
+        let state = engine.dispute(1);
+        assert!(approx_eq!(f64, state.disputed, 8., ulps = 1));
+        assert!(approx_eq!(f64, state.charged_back, 2., ulps = 1));
+
+        engine
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 1,
+                amount: Some(5.),
+            })
+            .unwrap();
+        assert_eq!(balances(&engine), (5., 3., 8.));
+        match engine.apply(withdrawal(1, 2, 1.)).unwrap_err() {
+            Error::Client(client::Error::Locked(1)) => (),
+            e => panic!("expected client::Error::Locked, got {:?}", e),
+        }
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+        assert_eq!(balances(&engine), (5., 0., 5.));
+        assert!(engine.client(1).unwrap().is_locked());
+        assert!(!engine.dispute(1).is_open());
+
+        // the same for a disputed withdrawal
+        let mut engine = Engine::new(HashMap::<u16, Client>::new());
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine.apply(withdrawal(1, 2, 4.)).unwrap();
+        engine.apply(dispute(1, 2, None)).unwrap();
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 2,
+                amount: Some(1.),
+            })
+            .unwrap();
+        assert_eq!(balances(&engine), (7., 3., 10.));
+        assert!(engine.client(1).unwrap().is_locked());
+        engine
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 2,
+                amount: None,
+            })
+            .unwrap();
+        assert_eq!(balances(&engine), (7., 0., 7.));
+        assert!(engine.client(1).unwrap().is_locked());
+    }
+
+    #[test]
+    fn test_engine_partial_disputes_add_up() {
+        let mut engine = Engine::new(VecStore::new());
+        engine.apply(deposit(1, 1, 0.3)).unwrap();
+        engine.apply(dispute(1, 1, Some(0.1))).unwrap();
+        engine.apply(dispute(1, 1, Some(0.2))).unwrap();
+        match engine.apply(dispute(1, 1, None)).unwrap_err() {
+            Error::AlreadyDisputed(1) => (),
+            e => panic!("expected Error::AlreadyDisputed, got {:?}", e),
+        }
+
+        engine
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+        assert!(!engine.dispute(1).is_open());
+        assert!(approx_eq!(
+            f64,
+            engine.client(1).unwrap().available(),
+            0.3,
+            ulps = 1
+        ));
+    }
+
+    fn withdrawal(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::Withdrawal {
+            client,
+            tx,
+            amount,
+            dispute: false,
+        }
+    }
+
+    fn disputed_withdrawal(policy: Policy) -> Engine<VecStore> {
+        let mut engine = Engine::new(VecStore::new()).with_policy(policy);
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine.apply(withdrawal(1, 2, 4.)).unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_engine_withdrawals_not_disputable() {
+        let mut engine = disputed_withdrawal(Policy {
+            withdrawal_disputes: WithdrawalDisputes::NotDisputable,
+            ..Policy::default()
+        });
+        match engine.apply(dispute(1, 2, None)).unwrap_err() {
+            Error::NotDisputable(2) => (),
+            e => panic!("expected Error::NotDisputable, got {:?}", e),
+        }
+        match engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 2,
+                amount: None,
+            })
+            .unwrap_err()
+        {
+            Error::NotDisputed(2) => (),
+            e => panic!("expected Error::NotDisputed, got {:?}", e),
+        }
+
+        let client = engine.client(1).unwrap();
+        assert_eq!((client.available(), client.held()), (6., 0.));
+        assert!(!client.is_locked());
+
+        // deposits are still disputable
+        engine.apply(dispute(1, 1, Some(1.))).unwrap();
+    }
+
+    #[test]
+    fn test_engine_withdrawal_refund() {
+        let policy = Policy {
+            withdrawal_disputes: WithdrawalDisputes::Refund,
+            ..Policy::default()
+        };
+        assert_eq!(policy, Policy::default());
+
+        let mut engine = disputed_withdrawal(policy);
+        engine.apply(dispute(1, 2, None)).unwrap();
+        let client = engine.client(1).unwrap();
+        assert_eq!((client.available(), client.held()), (6., 4.));
+
+        // resolved in favour of the bank: the credit is taken back
+        engine
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 2,
+                amount: None,
+            })
+            .unwrap();
+        let client = engine.client(1).unwrap();
+        assert_eq!((client.available(), client.held()), (6., 0.));
+
+        // charged back: the customer gets the withdrawal refunded
+        engine.apply(dispute(1, 2, None)).unwrap();
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 2,
+                amount: None,
+            })
+            .unwrap();
+        let client = engine.client(1).unwrap();
+        assert_eq!(
+            (client.available(), client.held(), client.total()),
+            (10., 0., 10.)
+        );
+        assert!(client.is_locked());
+    }
+
+    // deposit 10, withdraw 6, dispute the deposit
+    fn shortfall(policy: DisputeShortfall) -> (Engine<VecStore>, Result<()>) {
+        let mut engine = Engine::new(VecStore::new()).with_policy(Policy {
+            dispute_shortfall: policy,
+            ..Policy::default()
+        });
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine.apply(withdrawal(1, 2, 6.)).unwrap();
+        let res = engine.apply(dispute(1, 1, None));
+        (engine, res)
+    }
+
+    fn settle(engine: &mut Engine<VecStore>, chargeback: bool) {
+        let (client, tx, amount) = (1, 1, None);
+        let tx = if chargeback {
+            Transaction::Chargeback { client, tx, amount }
+        } else {
+            Transaction::Resolve { client, tx, amount }
+        };
+        engine.apply(tx).unwrap();
+    }
+
+    fn balances(engine: &Engine<VecStore>) -> (f64, f64, f64) {
+        let client = engine.client(1).unwrap();
+        (client.available(), client.held(), client.total())
+    }
+
+    #[test]
+    fn test_engine_shortfall_reject() {
+        let (mut engine, res) = shortfall(DisputeShortfall::Reject);
+        match res.unwrap_err() {
+            Error::Client(client::Error::InsufficientFunds(1, _)) => (),
+            e => panic!("expected Error::InsufficientFunds, got {:?}", e),
+        }
+        assert_eq!(balances(&engine), (4., 0., 4.));
+        assert!(!engine.dispute(1).is_open());
+        assert_eq!(engine.tx(1), Some(&deposit(1, 1, 10.)));
+
+        match engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap_err()
+        {
+            Error::NotDisputed(1) => (),
+            e => panic!("expected Error::NotDisputed, got {:?}", e),
+        }
+        assert!(!engine.client(1).unwrap().is_locked());
+    }
+
+    #[test]
+    fn test_engine_shortfall_hold_available() {
+        let (mut engine, res) = shortfall(DisputeShortfall::HoldAvailable);
+        res.unwrap();
+        assert_eq!(balances(&engine), (0., 4., 4.));
+        assert_eq!(engine.dispute(1).disputed, 10.);
+        assert_eq!(engine.dispute(1).held, 4.);
+
+        settle(&mut engine, false);
+        assert_eq!(balances(&engine), (4., 0., 4.));
+
+        let (mut engine, _) = shortfall(DisputeShortfall::HoldAvailable);
+        settle(&mut engine, true);
+        assert_eq!(balances(&engine), (0., 0., 0.));
+        assert_eq!(engine.dispute(1).charged_back, 10.);
+        assert_eq!(engine.client(1).unwrap().debt(), 0.);
+    }
+
+    #[test]
+    fn test_engine_shortfall_allow_negative() {
+        let (mut engine, res) = shortfall(DisputeShortfall::AllowNegative);
+        res.unwrap();
+        assert_eq!(balances(&engine), (0., 10., 4.));
+        assert_eq!(engine.client(1).unwrap().debt(), 6.);
+        assert_eq!(engine.dispute(1).credit, 6.);
+
+        settle(&mut engine, false);
+        assert_eq!(balances(&engine), (4., 0., 4.));
+        assert_eq!(engine.client(1).unwrap().debt(), 0.);
+
+        let (mut engine, _) = shortfall(DisputeShortfall::AllowNegative);
+        settle(&mut engine, true);
+        assert_eq!(balances(&engine), (0., 0., -6.));
+        assert_eq!(engine.client(1).unwrap().debt(), 6.);
+        assert!(engine.client(1).unwrap().is_locked());
+    }
+
+    #[test]
+    fn test_engine_failed_settle_keeps_dispute() {
+        // the dispute says 5 is held for tx 1, but the client only has a
+        // reserve, so neither a resolve nor a chargeback can take it
+        let mut clients = VecStore::new();
+        clients.insert(Client::from_parts(1, 0., 5., false));
+        let state = DisputeState {
+            disputed: 5.,
+            held: 5.,
+            ..DisputeState::default()
+        };
+        let root = Transaction::Deposit {
+            client: 1,
+            tx: 1,
+            amount: 5.,
+            dispute: true,
+        };
+        let mut engine =
+            Engine::with_history(clients, vec![root.clone()], vec![(1, state)]);
+
+        let (client, tx) = (1, 1);
+        for settle in [
+            Transaction::Resolve {
+                client,
+                tx,
+                amount: None,
+            },
+            Transaction::Chargeback {
+                client,
+                tx,
+                amount: None,
+            },
+        ] {
+            match engine.apply(settle).unwrap_err() {
+                Error::Client(client::Error::InsufficientFunds(1, _)) => (),
+                e => panic!("expected Error::InsufficientFunds, got {:?}", e),
+            }
+            assert_eq!(engine.dispute(1), state);
+            assert_eq!(engine.tx(1), Some(&root));
+            let client = engine.client(1).unwrap();
+            assert_eq!((client.available(), client.held()), (0., 5.));
+            assert!(!client.is_locked());
+        }
+    }
+
+    #[test]
+    fn test_engine_debt_recovery() {
+        let cases = [
+            ("first", (0., 1.), (3., 0.)),
+            ("share:0.25", (3., 4.), (6., 3.)),
+            ("never", (4., 5.), (8., 5.)),
+        ];
+        for (recovery, first, second) in cases.iter() {
+            let mut clients = VecStore::new();
+            clients.insert(Client::new(1, 0.).with_debt(5.));
+            let mut engine = Engine::new(clients).with_policy(Policy {
+                debt_recovery: recovery.parse().unwrap(),
+                ..Policy::default()
+            });
+            let debt = |engine: &Engine<VecStore>| {
+                let client = engine.client(1).unwrap();
+                (client.available(), client.debt())
+            };
+
+            engine.apply(deposit(1, 1, 4.)).unwrap();
+            assert_eq!(debt(&engine), *first, "{}", recovery);
+            engine.apply(deposit(1, 2, 4.)).unwrap();
+            assert_eq!(debt(&engine), *second, "{}", recovery);
+            assert_eq!(engine.client(1).unwrap().total(), 3.);
+        }
+        assert!("share:2".parse::<DebtRecovery>().is_err());
+
+        // a deposit while a dispute is held on credit pays part of it, and
+        // the resolve pays the rest
+        let (mut engine, _) = shortfall(DisputeShortfall::AllowNegative);
+        engine.apply(deposit(1, 3, 4.)).unwrap();
+        assert_eq!(balances(&engine), (0., 10., 8.));
+        assert_eq!(engine.client(1).unwrap().debt(), 2.);
+        settle(&mut engine, false);
+        assert_eq!(balances(&engine), (8., 0., 8.));
+        assert_eq!(engine.client(1).unwrap().debt(), 0.);
+    }
+
+    fn admin(client: u16, tx: u32, action: AdminAction) -> Transaction {
+        Transaction::Admin {
+            client,
+            tx,
+            action,
+            actor: Some(String::from("ops")),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn test_engine_admin() {
+        let mut engine = Engine::new(VecStore::new());
+        match engine
+            .apply(admin(1, 100, AdminAction::Freeze))
+            .unwrap_err()
+        {
+            Error::NoAccount(1) => (),
+            e => panic!("expected Error::NoAccount, got {:?}", e),
+        }
+
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine.apply(admin(1, 101, AdminAction::Freeze)).unwrap();
+        match engine.apply(withdrawal(1, 2, 1.)).unwrap_err() {
+            Error::NotPermitted {
+                client: 1,
+                status: Status::Frozen,
+                op: "withdrawal",
+            } => (),
+            e => panic!("expected Error::NotPermitted, got {:?}", e),
+        }
+        engine.apply(deposit(1, 3, 1.)).unwrap();
+        engine.apply(admin(1, 102, AdminAction::Unlock)).unwrap();
+        engine.apply(withdrawal(1, 4, 1.)).unwrap();
+
+        // a chargeback lock can be lifted, but not eased to a freeze
+        engine.apply(dispute(1, 3, None)).unwrap();
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 3,
+                amount: None,
+            })
+            .unwrap();
+        assert!(engine.apply(admin(1, 103, AdminAction::Freeze)).is_err());
+        engine.apply(admin(1, 104, AdminAction::Unlock)).unwrap();
+        assert_eq!(engine.client(1).unwrap().status(), Status::Active);
+
+        match engine.apply(admin(1, 105, AdminAction::Close)).unwrap_err() {
+            Error::NotClosable { client: 1, .. } => (),
+            e => panic!("expected Error::NotClosable, got {:?}", e),
+        }
+        engine.apply(withdrawal(1, 5, 9.)).unwrap();
+        engine.apply(admin(1, 106, AdminAction::Close)).unwrap();
+        match engine.apply(deposit(1, 6, 1.)).unwrap_err() {
+            Error::Client(client::Error::Closed(1)) => (),
+            e => panic!("expected Error::Closed, got {:?}", e),
+        }
+        assert!(engine.apply(admin(1, 107, AdminAction::Unlock)).is_err());
+
+        let audit: Vec<(u32, &str, Status, Status)> = engine
+            .audit()
+            .iter()
+            .map(|entry| (entry.tx, entry.action, entry.from, entry.to))
+            .collect();
+        assert_eq!(
+            audit,
+            vec![
+                (101, "freeze", Status::Active, Status::Frozen),
+                (102, "unlock", Status::Frozen, Status::Active),
+                (104, "unlock", Status::Locked, Status::Active),
+                (106, "close", Status::Active, Status::Closed),
+            ]
+        );
+        assert_eq!(engine.audit()[0].actor.as_deref(), Some("ops"));
+    }
+
+    fn capture(client: u16, tx: u32, amount: Option<f64>) -> Transaction {
+        Transaction::Capture { client, tx, amount }
+    }
+
+    #[test]
+    fn test_engine_authorizations() {
+        let mut engine = Engine::new(VecStore::new());
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        let authorize = |tx, amount| Transaction::Authorize {
+            client: 1,
+            tx,
+            amount,
+        };
+        engine.apply(authorize(2, 4.)).unwrap();
+        assert_eq!(balances(&engine), (6., 4., 10.));
+        match engine.apply(authorize(3, 7.)).unwrap_err() {
+            Error::Client(client::Error::InsufficientFunds(1, _)) => (),
+            e => panic!("expected Error::InsufficientFunds, got {:?}", e),
+        }
+
+        engine.apply(capture(1, 2, Some(1.))).unwrap();
+        assert_eq!(balances(&engine), (6., 3., 9.));
+        match engine.apply(capture(1, 2, Some(5.))).unwrap_err() {
+            Error::ExceedsAuthorized { tx: 2, .. } => (),
+            e => panic!("expected Error::ExceedsAuthorized, got {:?}", e),
+        }
+        match engine.apply(capture(2, 2, None)).unwrap_err() {
+            Error::WrongClient { owner: 1, .. } => (),
+            e => panic!("expected Error::WrongClient, got {:?}", e),
+        }
+        engine.apply(capture(1, 2, None)).unwrap();
+        assert_eq!(balances(&engine), (6., 0., 6.));
+        assert_eq!(engine.tx(2), Some(&withdrawal(1, 2, 4.)));
+        assert_eq!(engine.authorization(2).unwrap().captured, 4.);
+        match engine.apply(Transaction::Void { client: 1, tx: 2 }) {
+            Err(Error::NotAuthorized(2)) => (),
+            res => panic!("expected Error::NotAuthorized, got {:?}", res),
+        }
+
+        engine.apply(authorize(4, 2.)).unwrap();
+        engine
+            .apply(Transaction::Void { client: 1, tx: 4 })
+            .unwrap();
+        assert_eq!(balances(&engine), (6., 0., 6.));
+        assert_eq!(engine.authorization(4).unwrap().voided, 2.);
+        match engine.apply(deposit(1, 4, 1.)).unwrap_err() {
+            Error::DuplicateTx(4) => (),
+            e => panic!("expected Error::DuplicateTx, got {:?}", e),
+        }
+
+        // the captured withdrawal can be disputed like any other
+        engine.apply(dispute(1, 2, None)).unwrap();
+        assert_eq!(balances(&engine), (6., 4., 10.));
+        let holds = engine.client(1).unwrap().holds();
+        assert_eq!(holds.len(), 1);
+        assert_eq!(
+            (holds[0].tx, holds[0].reason, holds[0].amount),
+            (Some(2), HoldReason::Dispute, 4.)
+        );
+    }
+
+    #[test]
+    fn test_engine_authorize_invalid_amount() {
+        let mut engine = Engine::new(VecStore::new());
+        engine.apply(deposit(1, 1, 1.)).unwrap();
+        for (tx, amount) in [(2, -5.), (3, 0.), (4, f64::NAN)].iter() {
+            match engine.apply(Transaction::Authorize {
+                client: 1,
+                tx: *tx,
+                amount: *amount,
+            }) {
+                Err(Error::InvalidAmount { .. }) => (),
+                res => panic!("expected Error::InvalidAmount, got {:?}", res),
+            }
+            assert!(engine.authorization(*tx).is_none());
+        }
+        assert_eq!(balances(&engine), (1., 0., 1.));
+    }
+
+    #[test]
+    fn test_engine_authorization_expiry() {
+        let mut engine = Engine::new(VecStore::new()).with_policy(Policy {
+            authorization_ttl: Some(2),
+            ..Policy::default()
+        });
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine
+            .apply(Transaction::Authorize {
+                client: 1,
+                tx: 2,
+                amount: 5.,
+            })
+            .unwrap();
+        engine.apply(deposit(1, 3, 1.)).unwrap();
+        assert_eq!(balances(&engine), (6., 5., 11.));
+        engine.apply(deposit(1, 4, 1.)).unwrap();
+        assert_eq!(balances(&engine), (12., 0., 12.));
+        assert_eq!(engine.authorization(2).unwrap().expired, 5.);
+        match engine.apply(capture(1, 2, None)).unwrap_err() {
+            Error::NotAuthorized(2) => (),
+            e => panic!("expected Error::NotAuthorized, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_engine_review() {
+        let mut engine = Engine::new(VecStore::new());
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine.apply(dispute(1, 1, Some(4.))).unwrap();
+        engine.apply(admin(1, 100, AdminAction::Review)).unwrap();
+        assert_eq!(engine.client(1).unwrap().status(), Status::UnderReview);
+        assert!(!engine.client(1).unwrap().is_locked());
+
+        for tx in [deposit(1, 2, 1.), withdrawal(1, 3, 1.)].iter() {
+            match engine.apply(tx.clone()).unwrap_err() {
+                Error::NotPermitted {
+                    client: 1,
+                    status: Status::UnderReview,
+                    ..
+                } => (),
+                e => panic!("expected Error::NotPermitted, got {:?}", e),
+            }
+        }
+
+        // disputes carry on while the account is reviewed, and a chargeback
+        // still locks it
+        engine
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+        engine.apply(dispute(1, 1, Some(2.))).unwrap();
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+        assert_eq!(engine.client(1).unwrap().status(), Status::Locked);
+        assert!(engine.apply(admin(1, 101, AdminAction::Review)).is_err());
+
+        engine.apply(admin(1, 102, AdminAction::Unlock)).unwrap();
+        engine.apply(withdrawal(1, 4, 8.)).unwrap();
+        assert_eq!(engine.client(1).unwrap().total(), 0.);
+    }
+
+    #[test]
+    fn test_engine_expiry_balance_changed() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new(VecStore::new()).with_policy(Policy {
+            authorization_ttl: Some(1),
+            ..Policy::default()
+        });
+        let seen = Rc::clone(&events);
+        engine.subscribe(Box::new(move |event: &Event| {
+            seen.borrow_mut().push(event.clone())
+        }));
+
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine
+            .apply(Transaction::Authorize {
+                client: 1,
+                tx: 2,
+                amount: 2.,
+            })
+            .unwrap();
+        // expires the authorization, then is rejected without changing
+        // the balances again
+        engine.apply(withdrawal(1, 3, 20.)).unwrap_err();
+
+        let balances: Vec<_> = events
+            .borrow()
+            .iter()
+            .filter_map(|event| match *event {
+                Event::BalanceChanged {
+                    tx, available, held, ..
+                } => Some((tx, available, held)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(balances, vec![(1, 10., 0.), (2, 8., 2.), (2, 10., 0.)]);
+    }
+
+    #[test]
+    fn test_engine_events() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new(VecStore::new()).with_policy(Policy {
+            authorization_ttl: Some(2),
+            ..Policy::default()
+        });
+        let seen = Rc::clone(&events);
+        engine.subscribe(Box::new(move |event: &Event| {
+            seen.borrow_mut().push(event.clone())
+        }));
+
+        engine.apply(deposit(1, 1, 10.)).unwrap();
+        engine.apply(withdrawal(1, 2, 20.)).unwrap_err();
+        engine
+            .apply(Transaction::Authorize {
+                client: 1,
+                tx: 3,
+                amount: 2.,
+            })
+            .unwrap();
+        engine.apply(dispute(1, 1, Some(4.))).unwrap();
+        engine
+            .apply(Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+        engine.apply(admin(1, 4, AdminAction::Unlock)).unwrap();
+
+        let (balances, events): (Vec<Event>, Vec<Event>) =
+            events.borrow().iter().cloned().partition(|event| {
+                matches!(event, Event::BalanceChanged { .. })
+            });
+        let balances: Vec<_> = balances
+            .into_iter()
+            .map(|event| match event {
+                Event::BalanceChanged {
+                    tx,
+                    available,
+                    held,
+                    total,
+                    locked,
+                    ..
+                } => (tx, available, held, total, locked),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            balances,
+            vec![
+                (1, 10., 0., 10., false),
+                (3, 8., 2., 10., false),
+                (1, 4., 6., 10., false),
+                (3, 6., 4., 10., false),
+                (1, 6., 0., 6., true),
+                (4, 6., 0., 6., false),
+            ]
+        );
+
+        assert_eq!(events.len(), 8);
+        assert_eq!(
+            events[0],
+            Event::Deposited {
+                client: 1,
+                tx: 1,
+                amount: 10.
+            }
+        );
+        match &events[1] {
+            Event::Rejected {
+                client: 1,
+                tx: 2,
+                ty: "withdrawal",
+                kind: "insufficient_funds",
+                ..
+            } => (),
+            e => panic!("expected Event::Rejected, got {:?}", e),
+        }
+        assert_eq!(
+            events[2..],
+            [
+                Event::Authorized {
+                    client: 1,
+                    tx: 3,
+                    amount: 2.
+                },
+                Event::DisputeOpened {
+                    client: 1,
+                    tx: 1,
+                    amount: 4.
+                },
+                // the authorization expires before the chargeback applies
+                Event::Expired {
+                    client: 1,
+                    tx: 3,
+                    amount: 2.
+                },
+                Event::ChargedBack {
+                    client: 1,
+                    tx: 1,
+                    amount: 4.
+                },
+                Event::AccountLocked { client: 1, tx: 1 },
+                Event::StatusChanged {
+                    client: 1,
+                    tx: 4,
+                    from: Status::Locked,
+                    to: Status::Active,
+                },
+            ]
+        );
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+// Reasons a transaction is rejected by the engine. Invariant violations that
+// point at a bug in the engine itself still panic
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Client(#[from] client::Error),
+
+    #[error("another transaction already exists with tx id {0}")]
+    DuplicateTx(u32),
+
+    #[error(
+        "client {0} is a new client with no transaction history and cannot \
+        have a dispute"
+    )]
+    UnknownClient(u16),
+
+    #[error("transaction {tx} for client {client} does not exist")]
+    UnknownTx { client: u16, tx: u32 },
+
+    #[error("tx {tx} is owned by client {owner}, not client {client}")]
+    WrongClient { client: u16, tx: u32, owner: u16 },
+
+    #[error("tx {0} is already being disputed")]
+    AlreadyDisputed(u32),
+
+    #[error("tx {0} is not being disputed")]
+    NotDisputed(u32),
+
+    #[error("amount {amount} referring to tx {tx} must be positive")]
+    InvalidAmount { tx: u32, amount: f64 },
+
+    #[error(
+        "cannot dispute {amount} of tx {tx}, only {remaining} is left to \
+        dispute"
+    )]
+    ExceedsDisputable {
+        tx: u32,
+        amount: f64,
+        remaining: f64,
+    },
+
+    #[error("cannot settle {amount} of tx {tx}, only {disputed} is disputed")]
+    ExceedsDisputed { tx: u32, amount: f64, disputed: f64 },
+
+    #[error("tx {0} is a withdrawal, which cannot be disputed")]
+    NotDisputable(u32),
+
+    #[error("client {0} does not exist")]
+    NoAccount(u16),
+
+    #[error("tx {0} has nothing left authorized")]
+    NotAuthorized(u32),
+
+    #[error(
+        "cannot capture {amount} of tx {tx}, only {remaining} is left \
+        authorized"
+    )]
+    ExceedsAuthorized {
+        tx: u32,
+        amount: f64,
+        remaining: f64,
+    },
+
+    #[error("{op} refused: client {client} is {status}")]
+    NotPermitted {
+        client: u16,
+        status: Status,
+        op: &'static str,
+    },
+
+    #[error(
+        "client {client} cannot be closed with {available} available, {held} \
+        held and {debt} owed"
+    )]
+    NotClosable {
+        client: u16,
+        available: f64,
+        held: f64,
+        debt: f64,
+    },
+}
+
+impl Error {
+    // Short stable name of the reason, for counting rejections
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Client(client::Error::Locked(_)) => "locked",
+            Error::Client(client::Error::Closed(_)) => "closed",
+            Error::Client(client::Error::InsufficientFunds(..)) => {
+                "insufficient_funds"
+            }
+            Error::DuplicateTx(_) => "duplicate_tx",
+            Error::UnknownClient(_) | Error::NoAccount(_) => "unknown_client",
+            Error::UnknownTx { .. } => "unknown_tx",
+            Error::WrongClient { .. } => "wrong_client",
+            Error::AlreadyDisputed(_) => "already_disputed",
+            Error::NotDisputed(_) => "not_disputed",
+            Error::InvalidAmount { .. } => "invalid_amount",
+            Error::ExceedsDisputable { .. } => "exceeds_disputable",
+            Error::ExceedsDisputed { .. } => "exceeds_disputed",
+            Error::NotDisputable(_) => "not_disputable",
+            Error::NotPermitted { .. } => "not_permitted",
+            Error::NotClosable { .. } => "not_closable",
+            Error::NotAuthorized(_) => "not_authorized",
+            Error::ExceedsAuthorized { .. } => "exceeds_authorized",
+        }
+    }
+}
+
+// What a dispute of a withdrawal means
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithdrawalDisputes {
+    // only deposits can be disputed. Disputes of withdrawals are rejected
+    NotDisputable,
+    // the customer claims they did not make the withdrawal. The disputed
+    // amount is credited back and held while the dispute is open. A resolve
+    // takes the credit back, a chargeback releases it to the customer and
+    // locks the account
+    Refund,
+}
+
+impl Default for WithdrawalDisputes {
+    fn default() -> Self {
+        WithdrawalDisputes::Refund
+    }
+}
+
+impl FromStr for WithdrawalDisputes {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "not-disputable" => Ok(WithdrawalDisputes::NotDisputable),
+            "refund" => Ok(WithdrawalDisputes::Refund),
+            _ => Err(format!("unknown withdrawal dispute policy: {}", s)),
+        }
+    }
+}
+
+// What a dispute of a deposit does when the client has less available than
+// the disputed amount, typically because part of the deposit was already
+// withdrawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisputeShortfall {
+    // reject the dispute with client::Error::InsufficientFunds
+    Reject,
+    // hold what is available. A resolve releases only what was held and a
+    // chargeback takes back only what was held
+    HoldAvailable,
+    // hold the full amount. The shortfall is held on credit and recorded as
+    // Client::debt. A resolve pays it back, a chargeback leaves it owed
+    AllowNegative,
+}
+
+impl Default for DisputeShortfall {
+    fn default() -> Self {
+        DisputeShortfall::Reject
+    }
+}
+
+impl FromStr for DisputeShortfall {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "reject" => Ok(DisputeShortfall::Reject),
+            "hold-available" => Ok(DisputeShortfall::HoldAvailable),
+            "allow-negative" => Ok(DisputeShortfall::AllowNegative),
+            _ => Err(format!("unknown dispute shortfall policy: {}", s)),
+        }
+    }
+}
+
+// How much of a deposit to a client in debt goes to paying it off. Only
+// what is left becomes available
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebtRecovery {
+    // all of it, until the debt is paid
+    First,
+    // this share of every deposit, between 0 and 1
+    Share(f64),
+    // none. The debt is only reported and collected some other way
+    Never,
+}
+
+impl Default for DebtRecovery {
+    fn default() -> Self {
+        DebtRecovery::First
+    }
+}
+
+impl DebtRecovery {
+    fn repayment(&self, deposit: f64) -> f64 {
+        match self {
+            DebtRecovery::First => deposit,
+            DebtRecovery::Share(share) => deposit * share,
+            DebtRecovery::Never => 0.,
+        }
+    }
+}
+
+impl FromStr for DebtRecovery {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        match s.split_once(':') {
+            None if s == "first" => Ok(DebtRecovery::First),
+            None if s == "never" => Ok(DebtRecovery::Never),
+            Some(("share", share)) => match share.parse::<f64>() {
+                Ok(share) if (0. ..=1.).contains(&share) => {
+                    Ok(DebtRecovery::Share(share))
+                }
+                _ => Err(format!("debt share must be 0 to 1, got {}", share)),
+            },
+            _ => Err(format!("unknown debt recovery policy: {}", s)),
+        }
+    }
+}
+
+// Business rules that differ between deployments
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Policy {
+    pub withdrawal_disputes: WithdrawalDisputes,
+    pub dispute_shortfall: DisputeShortfall,
+    pub debt_recovery: DebtRecovery,
+    // how many transactions an authorization stays open for before what it
+    // still reserves is released. Authorizations never expire without it
+    pub authorization_ttl: Option<u64>,
+}
+
+// How much of a deposit or withdrawal is under dispute and how much of it has
+// been charged back. Partial disputes add up to at most the full amount
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DisputeState {
+    pub disputed: f64,
+    // the part of `disputed` that is actually held, see DisputeShortfall
+    pub held: f64,
+    // the part of `held` that was held on credit and is owed by the client
+    pub credit: f64,
+    pub charged_back: f64,
+}
+
+impl DisputeState {
+    pub fn is_open(&self) -> bool {
+        self.disputed > EPSILON
+    }
+
+    // The part of a deposit or withdrawal of `total` that a new dispute for
+    // `amount` covers
+    fn claim(&self, tx: u32, total: f64, amount: Option<f64>) -> Result<f64> {
+        let remaining = total - self.disputed - self.charged_back;
+        match amount {
+            Some(amount) if amount <= 0. || amount.is_nan() => {
+                Err(Error::InvalidAmount { tx, amount })
+            }
+            _ if remaining <= EPSILON => Err(Error::AlreadyDisputed(tx)),
+            None => Ok(remaining),
+            Some(amount) if amount > remaining + EPSILON => {
+                Err(Error::ExceedsDisputable {
+                    tx,
+                    amount,
+                    remaining,
+                })
+            }
+            Some(amount) => Ok(amount.min(remaining)),
+        }
+    }
+
+    // The part of the disputed amount that a resolve or chargeback for
+    // `amount` settles
+    fn settle(&self, tx: u32, amount: Option<f64>) -> Result<f64> {
+        match amount {
+            Some(amount) if amount <= 0. || amount.is_nan() => {
+                Err(Error::InvalidAmount { tx, amount })
+            }
+            _ if !self.is_open() => Err(Error::NotDisputed(tx)),
+            None => Ok(self.disputed),
+            Some(amount) if amount > self.disputed + EPSILON => {
+                Err(Error::ExceedsDisputed {
+                    tx,
+                    amount,
+                    disputed: self.disputed,
+                })
+            }
+            Some(amount) => Ok(amount.min(self.disputed)),
+        }
+    }
+}
+
+// Funds reserved by an authorize and what became of them. Captured funds
+// left as a withdrawal under the authorization's tx id
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Authorization {
+    pub client: u16,
+    pub amount: f64,
+    pub captured: f64,
+    pub voided: f64,
+    pub expired: f64,
+}
+
+impl Authorization {
+    pub fn new(client: u16, amount: f64) -> Self {
+        Self {
+            client,
+            amount,
+            captured: 0.,
+            voided: 0.,
+            expired: 0.,
+        }
+    }
+
+    // What the authorization still reserves
+    pub fn remaining(&self) -> f64 {
+        self.amount - self.captured - self.voided - self.expired
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.remaining() > EPSILON
+    }
+}
+
+// Refuse `op` on a client whose status does not permit it. Locked and
+// closed clients refuse with the same error Client itself gives
+fn permit(client: &Client, op: Operation) -> Result<()> {
+    if client.status().permits(op) {
+        Ok(())
+    } else if client.is_locked() {
+        Err(client.refusal().into())
+    } else {
+        Err(Error::NotPermitted {
+            client: client.id(),
+            status: client.status(),
+            op: op.name(),
+        })
+    }
+}
+
+// An admin transaction the engine applied
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    pub tx: u32,
+    pub client: u16,
+    pub action: &'static str,
+    pub actor: Option<String>,
+    pub reason: Option<String>,
+    pub from: Status,
+    pub to: Status,
+}
+
+// Applies transactions to the clients in `S`, remembering deposits and
+// withdrawals so that they can later be disputed
+#[derive(Debug)]
+pub struct Engine<S> {
+    clients: S,
+    txs: HashMap<u32, Transaction>,
+    disputes: HashMap<u32, DisputeState>,
+    policy: Policy,
+    audit: Vec<AuditEntry>,
+    authorizations: HashMap<u32, Authorization>,
+    // open authorizations by the sequence number of the transaction that
+    // opened them, oldest first, for expiry
+    expiry: VecDeque<(u64, u32)>,
+    // transactions applied so far
+    seq: u64,
+    subscribers: Subscribers,
+}
+
+impl<S: ClientStore> Engine<S> {
+    pub fn new(clients: S) -> Self {
+        Self {
+            clients,
+            txs: HashMap::new(),
+            disputes: HashMap::new(),
+            policy: Policy::default(),
+            audit: Vec::new(),
+            authorizations: HashMap::new(),
+            expiry: VecDeque::new(),
+            seq: 0,
+            subscribers: Subscribers::default(),
+        }
+    }
+
+    // Resume from an earlier run. `txs` are the deposits and withdrawals
+    // that run recorded and `disputes` the dispute state of those that were
+    // ever disputed
+    pub fn with_history<I, D>(clients: S, txs: I, disputes: D) -> Self
+    where
+        I: IntoIterator<Item = Transaction>,
+        D: IntoIterator<Item = (u32, DisputeState)>,
+    {
+        Self {
+            clients,
+            txs: txs.into_iter().map(|tx| (tx.id(), tx)).collect(),
+            disputes: disputes.into_iter().collect(),
+            policy: Policy::default(),
+            audit: Vec::new(),
+            authorizations: HashMap::new(),
+            expiry: VecDeque::new(),
+            seq: 0,
+            subscribers: Subscribers::default(),
+        }
+    }
+
+    // Resume authorizations from an earlier run. Open ones expire as if they
+    // had just been made
+    pub fn with_authorizations<A>(mut self, authorizations: A) -> Self
+    where
+        A: IntoIterator<Item = (u32, Authorization)>,
+    {
+        for (tx, auth) in authorizations {
+            if auth.is_open() {
+                self.expiry.push_back((self.seq, tx));
+            }
+            self.authorizations.insert(tx, auth);
+        }
+        self
+    }
+
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    pub fn clients(&self) -> &S {
+        &self.clients
+    }
+
+    pub fn into_clients(self) -> S {
+        self.clients
+    }
+
+    pub fn client(&self, id: u16) -> Option<&Client> {
+        self.clients.get(id)
+    }
+
+    pub fn tx(&self, tx: u32) -> Option<&Transaction> {
+        self.txs.get(&tx)
+    }
+
+    pub fn dispute(&self, tx: u32) -> DisputeState {
+        self.disputes.get(&tx).copied().unwrap_or_default()
+    }
+
+    // Recorded deposits and withdrawals, in no particular order
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> + '_ {
+        self.txs.values()
+    }
+
+    pub fn authorization(&self, tx: u32) -> Option<&Authorization> {
+        self.authorizations.get(&tx)
+    }
+
+    // Every authorization, open or not, in no particular order
+    pub fn authorizations(
+        &self,
+    ) -> impl Iterator<Item = (u32, &Authorization)> + '_ {
+        self.authorizations.iter().map(|(tx, auth)| (*tx, auth))
+    }
+
+    // Open authorizations that the next transaction expires before it is
+    // processed. Those of clients that are locked by then are kept
+    pub fn expiring(
+        &self,
+    ) -> impl Iterator<Item = (u32, &Authorization)> + '_ {
+        let ttl = self.policy.authorization_ttl.map(|ttl| ttl.max(1));
+        let seq = self.seq + 1;
+        self.expiry
+            .iter()
+            .take_while(move |&&(at, _)| {
+                ttl.map_or(false, |ttl| seq - at >= ttl)
+            })
+            .filter_map(move |&(_, tx)| {
+                self.authorizations
+                    .get(&tx)
+                    .filter(|auth| auth.is_open())
+                    .map(|auth| (tx, auth))
+            })
+    }
+
+    // Admin transactions applied so far, in order
+    pub fn audit(&self) -> &[AuditEntry] {
+        &self.audit
+    }
+
+    // Deposits and withdrawals currently under dispute
+    pub fn open_disputes(&self) -> usize {
+        self.txs
+            .values()
+            .filter(|tx| match tx {
+                Transaction::Deposit { dispute, .. }
+                | Transaction::Withdrawal { dispute, .. } => *dispute,
+                _ => false,
+            })
+            .count()
+    }
+
+    // Tell `subscriber` about every event from now on
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    // Flush the subscribers once the run is over
+    pub fn flush_events(&mut self) -> io::Result<()> {
+        self.subscribers.flush()
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.subscribers.notify(&event);
+    }
+
+    pub fn apply(&mut self, tx_entry: Transaction) -> Result<()> {
+        // expiry emits its own balance changes, so it runs before the
+        // balances below are taken
+        self.seq += 1;
+        self.expire();
+        if self.subscribers.is_empty() {
+            return self.process(tx_entry);
+        }
+
+        let (client, tx) = (tx_entry.client(), tx_entry.id());
+        let status = self.client(client).map(Client::status);
+        let dispute = self.dispute(tx);
+        let auth = self.authorization(tx).copied();
+        let balance = self.balance_changed(client, tx);
+        let res = self.process(tx_entry.clone());
+        match &res {
+            Ok(()) => self.applied(&tx_entry, status, dispute, auth),
+            Err(e) => self.emit(Event::Rejected {
+                client,
+                tx,
+                ty: tx_entry.ty(),
+                kind: e.kind(),
+                reason: e.to_string(),
+            }),
+        }
+
+        // rejected transactions may still have opened an empty account
+        let now = self.balance_changed(client, tx);
+        if let Some(event) = now.filter(|now| Some(now) != balance.as_ref()) {
+            self.emit(event);
+        }
+        res
+    }
+
+    // A client's balances as they are now, as if `tx` had changed them
+    fn balance_changed(&self, client: u16, tx: u32) -> Option<Event> {
+        self.client(client).map(|client| Event::BalanceChanged {
+            client: client.id(),
+            tx,
+            available: client.available(),
+            held: client.held(),
+            total: client.total(),
+            locked: client.is_locked(),
+        })
+    }
+
+    // Emit what an applied transaction did
+    fn applied(
+        &mut self,
+        tx_entry: &Transaction,
+        status: Option<Status>,
+        dispute: DisputeState,
+        auth: Option<Authorization>,
+    ) {
+        let (client, tx) = (tx_entry.client(), tx_entry.id());
+        let after = self.dispute(tx);
+        let settled = |auth: Option<Authorization>| {
+            auth.map_or((0., 0.), |auth| (auth.captured, auth.voided))
+        };
+        let (captured, voided) = settled(auth);
+        let (now_captured, now_voided) =
+            settled(self.authorization(tx).copied());
+        let event = match *tx_entry {
+            Transaction::Deposit { amount, .. } => {
+                Some(Event::Deposited { client, tx, amount })
+            }
+            Transaction::Withdrawal { amount, .. } => {
+                Some(Event::Withdrawn { client, tx, amount })
+            }
+            Transaction::Dispute { .. } => Some(Event::DisputeOpened {
+                client,
+                tx,
+                amount: after.disputed - dispute.disputed,
+            }),
+            Transaction::Resolve { .. } => Some(Event::DisputeResolved {
+                client,
+                tx,
+                amount: dispute.disputed - after.disputed,
+            }),
+            Transaction::Chargeback { .. } => Some(Event::ChargedBack {
+                client,
+                tx,
+                amount: after.charged_back - dispute.charged_back,
+            }),
+            Transaction::Authorize { amount, .. } => {
+                Some(Event::Authorized { client, tx, amount })
+            }
+            Transaction::Capture { .. } => Some(Event::Captured {
+                client,
+                tx,
+                amount: now_captured - captured,
+            }),
+            Transaction::Void { .. } => Some(Event::Voided {
+                client,
+                tx,
+                amount: now_voided - voided,
+            }),
+            Transaction::Admin { .. } => None,
+        };
+        if let Some(event) = event {
+            self.emit(event);
+        }
+
+        let now = self.client(client).map(Client::status);
+        match (status, now) {
+            (Some(from), Some(Status::Locked)) if from != Status::Locked => {
+                self.emit(Event::AccountLocked { client, tx })
+            }
+            (Some(from), Some(to)) if from != to => {
+                self.emit(Event::StatusChanged {
+                    client,
+                    tx,
+                    from,
+                    to,
+                })
+            }
+            _ => (),
+        }
+    }
+
+    fn process(&mut self, tx_entry: Transaction) -> Result<()> {
+        match tx_entry {
+            Transaction::Deposit {
+                client, tx, amount, ..
+            } => {
+                if self.authorizations.contains_key(&tx) {
+                    return Err(Error::DuplicateTx(tx));
+                }
+                let slot = match self.txs.entry(tx) {
+                    Entry::Occupied(_) => return Err(Error::DuplicateTx(tx)),
+                    Entry::Vacant(slot) => slot,
+                };
+                let client = self.clients.get_or_create(client);
+                permit(client, Operation::Deposit)?;
+                client.add(amount)?;
+                client.repay(self.policy.debt_recovery.repayment(amount))?;
+                slot.insert(tx_entry);
+            }
+            Transaction::Withdrawal {
+                client, tx, amount, ..
+            } => {
+                if self.authorizations.contains_key(&tx) {
+                    return Err(Error::DuplicateTx(tx));
+                }
+                let slot = match self.txs.entry(tx) {
+                    Entry::Occupied(_) => return Err(Error::DuplicateTx(tx)),
+                    Entry::Vacant(slot) => slot,
+                };
+                let client = self.clients.get_or_create(client);
+                permit(client, Operation::Withdraw)?;
+                client.rm(amount)?;
+                slot.insert(tx_entry);
+            }
+
+            Transaction::Authorize { client, tx, amount } => {
+                if amount <= 0. || amount.is_nan() {
+                    return Err(Error::InvalidAmount { tx, amount });
+                }
+                if self.txs.contains_key(&tx) {
+                    return Err(Error::DuplicateTx(tx));
+                }
+                let slot = match self.authorizations.entry(tx) {
+                    Entry::Occupied(_) => return Err(Error::DuplicateTx(tx)),
+                    Entry::Vacant(slot) => slot,
+                };
+                let client = self.clients.get_or_create(client);
+                permit(client, Operation::Withdraw)?;
+                client.hold_for(tx, HoldReason::Authorization, amount)?;
+                slot.insert(Authorization::new(client.id(), amount));
+                self.expiry.push_back((self.seq, tx));
+            }
+
+            Transaction::Capture { client, tx, amount } => {
+                let (client, auth) = self.authorized(client, tx)?;
+                permit(client, Operation::Withdraw)?;
+                let remaining = auth.remaining();
+                let amount = match amount {
+                    Some(amount) if amount <= 0. || amount.is_nan() => {
+                        return Err(Error::InvalidAmount { tx, amount })
+                    }
+                    _ if !auth.is_open() => {
+                        return Err(Error::NotAuthorized(tx))
+                    }
+                    None => remaining,
+                    Some(amount) if amount > remaining + EPSILON => {
+                        return Err(Error::ExceedsAuthorized {
+                            tx,
+                            amount,
+                            remaining,
+                        })
+                    }
+                    Some(amount) => amount.min(remaining),
+                };
+
+                client.unhold_for(tx, HoldReason::Authorization, amount)?;
+                if let Err(e) = client.rm(amount) {
+                    panic!(
+                        "should be no error removing {} just unheld from \
+                        client {}: {:?}",
+                        amount,
+                        client.id(),
+                        e
+                    );
+                }
+                auth.captured += amount;
+
+                // every capture adds to the one withdrawal, which can be
+                // disputed like any other
+                let client = client.id();
+                match self.txs.entry(tx).or_insert(Transaction::Withdrawal {
+                    client,
+                    tx,
+                    amount: 0.,
+                    dispute: false,
+                }) {
+                    Transaction::Withdrawal { amount: total, .. } => {
+                        *total += amount
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            Transaction::Void { client, tx } => {
+                let (client, auth) = self.authorized(client, tx)?;
+                if !auth.is_open() {
+                    return Err(Error::NotAuthorized(tx));
+                }
+                let remaining = auth.remaining();
+                client.unhold_for(tx, HoldReason::Authorization, remaining)?;
+                auth.voided += remaining;
+            }
+
+            Transaction::Admin {
+                client,
+                tx,
+                action,
+                actor,
+                reason,
+            } => {
+                let client = self
+                    .clients
+                    .get_mut(client)
+                    .ok_or(Error::NoAccount(client))?;
+                let from = client.status();
+                let to = match (action, from) {
+                    // a closed account stays closed, and a lock has to be
+                    // lifted before it can be eased to a freeze or a review
+                    (_, Status::Closed)
+                    | (AdminAction::Freeze, Status::Locked)
+                    | (AdminAction::Review, Status::Locked) => {
+                        return Err(client.refusal().into())
+                    }
+                    (AdminAction::Unlock, _) => Status::Active,
+                    (AdminAction::Freeze, _) => Status::Frozen,
+                    (AdminAction::Review, _) => Status::UnderReview,
+                    (AdminAction::Close, _) => {
+                        let (available, held, debt) =
+                            (client.available(), client.held(), client.debt());
+                        if available.abs() > EPSILON
+                            || held.abs() > EPSILON
+                            || debt > EPSILON
+                        {
+                            return Err(Error::NotClosable {
+                                client: client.id(),
+                                available,
+                                held,
+                                debt,
+                            });
+                        }
+                        Status::Closed
+                    }
+                };
+
+                client.set_status(to);
+                self.audit.push(AuditEntry {
+                    tx,
+                    client: client.id(),
+                    action: action.name(),
+                    actor,
+                    reason,
+                    from,
+                    to,
+                });
+            }
+
+            // Initialize a dispute. Client wants to reverse all or part of a
+            // withdrawal or a deposit
+            Transaction::Dispute { client, tx, amount } => {
+                let mut state = self.dispute(tx);
+                let policy = self.policy;
+                let (client, root_tx) =
+                    self.referred(client, tx, Operation::Dispute)?;
+                match root_tx {
+                    Transaction::Deposit {
+                        amount: total,
+                        dispute,
+                        ..
+                    } => {
+                        let amount = state.claim(tx, *total, amount)?;
+
+                        // deposit 1, withdraw 1, dispute the deposit leaves
+                        // less available than the dispute wants to hold
+                        let held = match policy.dispute_shortfall {
+                            DisputeShortfall::Reject => {
+                                client.hold_for(
+                                    tx,
+                                    HoldReason::Dispute,
+                                    amount,
+                                )?;
+                                amount
+                            }
+                            DisputeShortfall::HoldAvailable => {
+                                let held =
+                                    amount.min(client.available().max(0.));
+                                client.hold_for(
+                                    tx,
+                                    HoldReason::Dispute,
+                                    held,
+                                )?;
+                                held
+                            }
+                            DisputeShortfall::AllowNegative => {
+                                state.credit +=
+                                    client.force_hold(tx, amount)?;
+                                amount
+                            }
+                        };
+                        state.disputed += amount;
+                        state.held += held;
+                        *dispute = true;
+                        self.disputes.insert(tx, state);
+                    }
+                    Transaction::Withdrawal {
+                        amount: total,
+                        dispute,
+                        ..
+                    } => {
+                        if policy.withdrawal_disputes
+                            == WithdrawalDisputes::NotDisputable
+                        {
+                            return Err(Error::NotDisputable(tx));
+                        }
+
+                        let amount = state.claim(tx, *total, amount)?;
+                        state.disputed += amount;
+                        state.held += amount;
+                        *dispute = true;
+
+                        // credit the withdrawal back, but keep it out of
+                        // reach until the dispute is settled
+                        client.add(amount)?;
+                        client
+                            .hold_for(tx, HoldReason::Dispute, amount)
+                            .unwrap();
+                        self.disputes.insert(tx, state);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            // The client loses. No-op the dispute and return the funds
+            // to their former state
+            Transaction::Resolve { client, tx, amount } => {
+                let mut state = self.dispute(tx);
+                let (client, root_tx) =
+                    self.referred(client, tx, Operation::Settle)?;
+                match root_tx {
+                    Transaction::Deposit { dispute, .. } => {
+                        // held funds are settled first. Whatever was not
+                        // held under DisputeShortfall::HoldAvailable has
+                        // nothing to release
+                        let amount = state.settle(tx, amount)?;
+                        let covered = amount.min(state.held);
+                        let credit = covered.min(state.credit);
+
+                        // funds held on credit go back to paying the debt.
+                        // Deposits may already have paid part of it
+                        client.unhold_for(tx, HoldReason::Dispute, covered)?;
+                        client.repay(credit)?;
+                        state.disputed -= amount;
+                        state.held -= covered;
+                        state.credit -= credit;
+                        *dispute = state.is_open();
+                        self.disputes.insert(tx, state);
+                    }
+                    Transaction::Withdrawal { dispute, .. } => {
+                        let amount = state.settle(tx, amount)?;
+                        state.disputed -= amount;
+                        state.held -= amount;
+                        *dispute = state.is_open();
+
+                        // this must panic b.c. we added funds in to hold
+                        // at the initial dispute as a way of saying
+                        // "let's pretend the withdrawal never happened
+                        // for now until the dispute is settled". Taking
+                        // them out of the hold directly works for a client
+                        // locked by a partial chargeback too
+                        if let Err(e) = client.release(tx, amount) {
+                            panic!(
+                                "error releasing {}: {:?}, for client {:?}. \
+                                Initial dispute bug",
+                                amount, e, client
+                            );
+                        }
+                        self.disputes.insert(tx, state);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            // The client wins. Give them their money directly and lock the
+            // compromised account, even if part of the dispute is still open
+            Transaction::Chargeback { client, tx, amount } => {
+                let mut state = self.dispute(tx);
+                let (client, root_tx) =
+                    self.referred(client, tx, Operation::Settle)?;
+                match root_tx {
+                    Transaction::Deposit { dispute, .. } => {
+                        let amount = state.settle(tx, amount)?;
+                        let covered = amount.min(state.held);
+
+                        // only held funds can be taken back. Under
+                        // DisputeShortfall::AllowNegative they may have been
+                        // held on credit, which stays owed as debt
+                        client.release(tx, covered)?;
+                        state.disputed -= amount;
+                        state.held -= covered;
+                        state.credit -= covered.min(state.credit);
+                        state.charged_back += amount;
+                        *dispute = state.is_open();
+                        client.lock();
+                        self.disputes.insert(tx, state);
+                    }
+                    Transaction::Withdrawal { dispute, .. } => {
+                        let amount = state.settle(tx, amount)?;
+                        state.disputed -= amount;
+                        state.held -= amount;
+                        state.charged_back += amount;
+                        *dispute = state.is_open();
+
+                        // the withdrawal was credited back and held on the
+                        // initial dispute. Refund the customer by releasing
+                        // the credit. unhold() should never fail
+                        if let Err(e) =
+                            client.unhold_for(tx, HoldReason::Dispute, amount)
+                        {
+                            panic!(
+                                "{:?} should have enough held funds from \
+                                initial dispute to unhold {}: {:?}",
+                                client, amount, e
+                            );
+                        }
+                        client.lock();
+                        self.disputes.insert(tx, state);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Release what authorizations older than the policy's ttl still reserve
+    fn expire(&mut self) {
+        let ttl = match self.policy.authorization_ttl {
+            Some(ttl) => ttl.max(1),
+            None => return,
+        };
+        while let Some(&(seq, tx)) = self.expiry.front() {
+            if self.seq - seq < ttl {
+                break;
+            }
+            self.expiry.pop_front();
+
+            let auth = match self.authorizations.get_mut(&tx) {
+                Some(auth) if auth.is_open() => auth,
+                _ => continue,
+            };
+            let remaining = auth.remaining();
+            let released =
+                self.clients.get_mut(auth.client).map_or(false, |client| {
+                    client
+                        .unhold_for(tx, HoldReason::Authorization, remaining)
+                        .is_ok()
+                });
+            if released {
+                auth.expired += remaining;
+                let client = auth.client;
+                self.emit(Event::Expired {
+                    client,
+                    tx,
+                    amount: remaining,
+                });
+                if let Some(event) = self.balance_changed(client, tx) {
+                    self.emit(event);
+                }
+            } else {
+                // locked accounts keep the reservation. Try again a ttl
+                // later
+                self.expiry.push_back((self.seq, tx));
+            }
+        }
+    }
+
+    // Look up the client and the authorization a capture or void refers to
+    fn authorized(
+        &mut self,
+        client: u16,
+        tx: u32,
+    ) -> Result<(&mut Client, &mut Authorization)> {
+        let auth = self
+            .authorizations
+            .get_mut(&tx)
+            .ok_or(Error::UnknownTx { client, tx })?;
+        if auth.client != client {
+            return Err(Error::WrongClient {
+                client,
+                tx,
+                owner: auth.client,
+            });
+        }
+        let client = self
+            .clients
+            .get_mut(client)
+            .ok_or(Error::UnknownClient(client))?;
+        Ok((client, auth))
+    }
+
+    // Look up the client and the deposit or withdrawal that a dispute,
+    // resolve or chargeback refers to
+    fn referred(
+        &mut self,
+        client: u16,
+        tx: u32,
+        op: Operation,
+    ) -> Result<(&mut Client, &mut Transaction)> {
+        let client = self
+            .clients
+            .get_mut(client)
+            .ok_or(Error::UnknownClient(client))?;
+        permit(client, op)?;
+
+        let root_tx = self.txs.get_mut(&tx).ok_or(Error::UnknownTx {
+            client: client.id(),
+            tx,
+        })?;
+
+        match root_tx {
+            Transaction::Deposit {
+                client: root_id,
+                tx: root_tx_id,
+                ..
+            }
+            | Transaction::Withdrawal {
+                client: root_id,
+                tx: root_tx_id,
+                ..
+            } => {
+                if *root_id != client.id() {
+                    return Err(Error::WrongClient {
+                        client: client.id(),
+                        tx,
+                        owner: *root_id,
+                    });
+                }
+
+                assert_eq!(
+                    *root_tx_id, tx,
+                    "expected dispute tx id to equal the id of the tx being \
+                    referred to. Transactions might be stored in the engine \
+                    wrong"
+                );
+            }
+            _ => panic!(
+                "expected root transaction with id {} to be a deposit or \
+                withdrawal type. Transactions in the engine must never be a \
+                referring type",
+                tx
+            ),
+        }
+
+        Ok((client, root_tx))
+    }
+}
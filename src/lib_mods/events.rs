@@ -0,0 +1,265 @@
+use crate::client::Status;
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, EventLog, Subscriber};
+    use crate::{
+        client::Status,
+        engine::Engine,
+        store::VecStore,
+        transaction::Transaction,
+    };
+    use std::{fs::File, io::BufWriter};
+
+    #[test]
+    fn test_event_log() {
+        let mut log = EventLog::new(Vec::new());
+        log.notify(&Event::Deposited {
+            client: 1,
+            tx: 1,
+            amount: 2.5,
+        });
+        // balance changes are for --cdc and not written
+        log.notify(&Event::BalanceChanged {
+            client: 1,
+            tx: 1,
+            available: 2.5,
+            held: 0.,
+            total: 2.5,
+            locked: false,
+        });
+        log.notify(&Event::StatusChanged {
+            client: 1,
+            tx: 2,
+            from: Status::Locked,
+            to: Status::Active,
+        });
+        let out = String::from_utf8(log.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "{\"event\":\"deposited\",\"client\":1,\"tx\":1,\"amount\":2.5}\n\
+            {\"event\":\"status_changed\",\"client\":1,\"tx\":2,\
+            \"from\":\"locked\",\"to\":\"active\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_event_log_of_engine() {
+        // as --events sets it up
+        let path = std::env::temp_dir()
+            .join(format!("floof-events-{}.jsonl", std::process::id()));
+        let mut engine = Engine::new(VecStore::new());
+        let file = File::create(&path).unwrap();
+        engine.subscribe(Box::new(EventLog::new(BufWriter::new(file))));
+        engine
+            .apply(Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 2.5,
+                dispute: false,
+            })
+            .unwrap();
+        engine
+            .apply(Transaction::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 1.,
+                dispute: false,
+            })
+            .unwrap();
+        engine.flush_events().unwrap();
+        let out = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            out,
+            "{\"event\":\"deposited\",\"client\":1,\"tx\":1,\"amount\":2.5}\n\
+            {\"event\":\"withdrawn\",\"client\":1,\"tx\":2,\"amount\":1.0}\n"
+        );
+    }
+}
+
+// Something that happened to an account. `tx` is the transaction that
+// caused it, except for expired authorizations, where it is the
+// authorization
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Deposited {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    Withdrawn {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    // `amount` is what the dispute, resolve or chargeback covered
+    DisputeOpened {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    DisputeResolved {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    ChargedBack {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    Authorized {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    Captured {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    Voided {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    Expired {
+        client: u16,
+        tx: u32,
+        amount: f64,
+    },
+    AccountLocked {
+        client: u16,
+        tx: u32,
+    },
+    // any other change of account status, e.g. by an admin transaction
+    StatusChanged {
+        client: u16,
+        tx: u32,
+        from: Status,
+        to: Status,
+    },
+    // The balances of a client after `tx` changed any of them. A new
+    // account counts as a change
+    BalanceChanged {
+        client: u16,
+        tx: u32,
+        available: f64,
+        held: f64,
+        total: f64,
+        locked: bool,
+    },
+    // `kind` is the same as in the run summary, see engine::Error::kind
+    Rejected {
+        client: u16,
+        tx: u32,
+        #[serde(rename = "type")]
+        ty: &'static str,
+        kind: &'static str,
+        reason: String,
+    },
+}
+
+// Told about every event as the engine applies transactions, see
+// Engine::subscribe
+pub trait Subscriber {
+    fn notify(&mut self, event: &Event);
+
+    // Called once the run is over, e.g. to report write errors
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&Event)> Subscriber for F {
+    fn notify(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+// The subscribers of an engine
+#[derive(Default)]
+pub struct Subscribers(Vec<Box<dyn Subscriber>>);
+
+impl Subscribers {
+    pub fn push(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.0.push(subscriber);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn notify(&mut self, event: &Event) {
+        for subscriber in self.0.iter_mut() {
+            subscriber.notify(event);
+        }
+    }
+
+    // Flush every subscriber, returning the first error
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut res = Ok(());
+        for subscriber in self.0.iter_mut() {
+            let flushed = subscriber.flush();
+            if res.is_ok() {
+                res = flushed;
+            }
+        }
+        res
+    }
+}
+
+impl fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Subscribers({})", self.0.len())
+    }
+}
+
+// Writes every event but balance changes, which ChangeLog writes, as a line
+// of json. Writing stops at the first error, which flush returns
+#[derive(Debug)]
+pub struct EventLog<W> {
+    wtr: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> EventLog<W> {
+    pub fn new(wtr: W) -> Self {
+        Self { wtr, error: None }
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        Subscriber::flush(&mut self)?;
+        Ok(self.wtr)
+    }
+}
+
+impl<W: Write> Subscriber for EventLog<W> {
+    fn notify(&mut self, event: &Event) {
+        let cdc = matches!(event, Event::BalanceChanged { .. });
+        if self.error.is_some() || cdc {
+            return;
+        }
+        let res = serde_json::to_writer(&mut self.wtr, event)
+            .map_err(io::Error::from)
+            .and_then(|_| self.wtr.write_all(b"\n"));
+        if let Err(e) = res {
+            self.error = Some(e);
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.wtr.flush(),
+        }
+    }
+}
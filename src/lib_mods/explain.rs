@@ -0,0 +1,354 @@
+use crate::{
+    client::{HoldReason, Status},
+    engine::Engine,
+    input,
+    statement::{self, Entry},
+    store::ClientStore,
+    transaction::Transaction,
+};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use super::Explainer;
+    use crate::{client::Client, engine::Engine, transaction::Transaction};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_explain() {
+        let txs = vec![
+            Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 10.,
+                dispute: false,
+            },
+            Transaction::Deposit {
+                client: 2,
+                tx: 2,
+                amount: 5.,
+                dispute: false,
+            },
+            Transaction::Deposit {
+                client: 1,
+                tx: 4,
+                amount: 3.5,
+                dispute: false,
+            },
+            Transaction::Withdrawal {
+                client: 1,
+                tx: 3,
+                amount: 20.,
+                dispute: false,
+            },
+            Transaction::Dispute {
+                client: 1,
+                tx: 4,
+                amount: None,
+            },
+            Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: Some(2.),
+            },
+            Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            },
+        ];
+
+        let mut explainer =
+            Explainer::new(Engine::new(HashMap::<u16, Client>::new()), 1);
+        for (line, tx) in (2..).zip(txs) {
+            explainer.apply(Some(line), Ok(tx));
+        }
+        explainer.apply(
+            Some(9),
+            Err(std::io::Error::from(std::io::ErrorKind::InvalidData).into()),
+        );
+
+        assert_eq!(
+            explainer.finish(),
+            vec![
+                "line 2: deposit 1 added 10",
+                "line 4: deposit 4 added 3.5",
+                "line 5: withdrawal 3 of 20 rejected: client 1 has \
+                insufficient funds of 13.5",
+                "line 6: dispute of tx 4 held 3.5",
+                "line 7: dispute of tx 1 held 2",
+                "line 8: chargeback of tx 1 took back 2, the account is now \
+                locked",
+                "1 row could not be read and was skipped",
+                "client 1 ends with available 8, held 3.5, total 11.5",
+                "it is locked since line 8: chargeback of tx 1",
+                "3.5 is held for the dispute of tx 4, opened on line 6",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_withdrawal_disputes() {
+        let dispute = |tx, amount| Transaction::Dispute {
+            client: 1,
+            tx,
+            amount,
+        };
+        let txs = vec![
+            Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 10.,
+                dispute: false,
+            },
+            Transaction::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 4.,
+                dispute: false,
+            },
+            dispute(2, None),
+            Transaction::Resolve {
+                client: 1,
+                tx: 2,
+                amount: Some(1.),
+            },
+            Transaction::Chargeback {
+                client: 1,
+                tx: 2,
+                amount: None,
+            },
+        ];
+
+        let mut explainer =
+            Explainer::new(Engine::new(HashMap::<u16, Client>::new()), 1);
+        for (line, tx) in (2..).zip(txs) {
+            explainer.apply(Some(line), Ok(tx));
+        }
+        assert_eq!(
+            explainer.finish()[2..5],
+            [
+                "line 4: dispute of tx 2 held 4",
+                "line 5: resolve of tx 2 reinstated the withdrawal of 1",
+                "line 6: chargeback of tx 2 refunded 3 to the client, the \
+                account is now locked",
+            ]
+        );
+    }
+}
+
+// What happened to one client over a run, in words. Built from the same
+// statement entries and engine errors a normal run reports
+#[derive(Debug)]
+pub struct Explainer<S> {
+    engine: Engine<S>,
+    client: u16,
+    notes: Vec<String>,
+    // line that first held funds for a transaction
+    opened: HashMap<(u32, HoldReason), Option<u64>>,
+    // the last transaction that changed the account status
+    status_change: Option<String>,
+    unreadable: u64,
+}
+
+fn at(line: Option<u64>) -> String {
+    line.map_or_else(String::new, |line| format!("line {}: ", line))
+}
+
+// The transaction an entry is for, e.g. "chargeback of tx 7"
+fn title(entry: &Entry) -> String {
+    match entry.ty {
+        "dispute" | "resolve" | "chargeback" => {
+            format!("{} of tx {}", entry.ty, entry.tx)
+        }
+        "capture" | "void" => {
+            format!("{} of authorization {}", entry.ty, entry.tx)
+        }
+        "authorize" => format!("authorization {}", entry.tx),
+        ty => format!("{} {}", ty, entry.tx),
+    }
+}
+
+// What a transaction that was applied did, by its effect on the balances.
+// `withdrawal` says whether a dispute, resolve or chargeback is of a
+// withdrawal, which was credited back while disputed
+fn describe(entry: &Entry, withdrawal: bool) -> String {
+    let (held, total) = (entry.held_change, entry.total_change);
+    match (entry.ty, withdrawal) {
+        ("deposit", _) => format!("{} added {}", title(entry), total),
+        ("withdrawal", _) | ("capture", _) => {
+            format!("{} took {}", title(entry), -total)
+        }
+        ("chargeback", false) => {
+            format!("{} took back {}", title(entry), -total)
+        }
+        ("chargeback", true) => {
+            format!("{} refunded {} to the client", title(entry), -held)
+        }
+        ("resolve", true) => {
+            format!("{} reinstated the withdrawal of {}", title(entry), -total)
+        }
+        ("dispute", _) => format!("{} held {}", title(entry), held),
+        ("authorize", _) => format!("{} reserved {}", title(entry), held),
+        ("resolve", false) | ("void", _) => {
+            format!("{} released {}", title(entry), -held)
+        }
+        _ => title(entry),
+    }
+}
+
+impl<S: ClientStore> Explainer<S> {
+    pub fn new(engine: Engine<S>, client: u16) -> Self {
+        Self {
+            engine,
+            client,
+            notes: Vec::new(),
+            opened: HashMap::new(),
+            status_change: None,
+            unreadable: 0,
+        }
+    }
+
+    // Apply a row. Only rows that name the client are explained. Rows that
+    // cannot be read are counted, since they may have been for it
+    pub fn apply(
+        &mut self,
+        line: Option<u64>,
+        row: input::Result<Transaction>,
+    ) {
+        let tx = match row {
+            Ok(tx) => tx,
+            Err(_) => {
+                self.unreadable += 1;
+                return;
+            }
+        };
+        if tx.client() != self.client {
+            // other clients' transactions still count, e.g. towards
+            // duplicate ids and authorization expiry
+            let entries = statement::apply(&mut self.engine, tx);
+            self.expired(line, &entries[..entries.len() - 1]);
+            return;
+        }
+
+        let status = self.engine.client(self.client).map(|c| c.status());
+        let withdrawal = matches!(
+            (&tx, self.engine.tx(tx.id())),
+            (
+                Transaction::Dispute { .. }
+                    | Transaction::Resolve { .. }
+                    | Transaction::Chargeback { .. },
+                Some(Transaction::Withdrawal { .. })
+            )
+        );
+        let mut entries = statement::apply(&mut self.engine, tx);
+        let entry = entries.pop().unwrap();
+        self.expired(line, &entries);
+        let note = match &entry.rejected {
+            Some(reason) => {
+                let amount = entry.amount.map_or_else(String::new, |amount| {
+                    format!(" of {}", amount)
+                });
+                format!("{}{} rejected: {}", title(&entry), amount, reason)
+            }
+            None => {
+                let reason = match entry.ty {
+                    "dispute" => Some(HoldReason::Dispute),
+                    "authorize" => Some(HoldReason::Authorization),
+                    _ => None,
+                };
+                if let Some(reason) = reason {
+                    self.opened.entry((entry.tx, reason)).or_insert(line);
+                }
+
+                let mut note = describe(&entry, withdrawal);
+                if status != Some(entry.status) && status.is_some() {
+                    note += &format!(", the account is now {}", entry.status);
+                    self.status_change =
+                        Some(format!("{}{}", at(line), title(&entry)));
+                }
+                note
+            }
+        };
+        self.notes.push(format!("{}{}", at(line), note));
+    }
+
+    // Note the authorizations of the client among `expired`, which expired
+    // before the row on `line` was processed
+    fn expired(&mut self, line: Option<u64>, expired: &[Entry]) {
+        let client = self.client;
+        for entry in expired.iter().filter(|e| e.client == client) {
+            self.notes.push(format!(
+                "{}authorization {} expired and released {}",
+                at(line),
+                entry.tx,
+                -entry.held_change
+            ));
+        }
+    }
+
+    // The story so far, followed by how the client ended up and why
+    pub fn finish(self) -> Vec<String> {
+        let mut notes = self.notes;
+        if self.unreadable > 0 {
+            notes.push(if self.unreadable == 1 {
+                String::from("1 row could not be read and was skipped")
+            } else {
+                format!(
+                    "{} rows could not be read and were skipped",
+                    self.unreadable
+                )
+            });
+        }
+
+        let client = match self.engine.client(self.client) {
+            Some(client) => client,
+            None => {
+                notes.push(format!("client {} has no account", self.client));
+                return notes;
+            }
+        };
+        notes.push(format!(
+            "client {} ends with available {}, held {}, total {}",
+            client.id(),
+            client.available(),
+            client.held(),
+            client.total()
+        ));
+        if client.debt() > 0. {
+            notes.push(format!("it owes {}", client.debt()));
+        }
+        if client.status() != Status::Active {
+            notes.push(match self.status_change {
+                Some(change) => {
+                    format!("it is {} since {}", client.status(), change)
+                }
+                None => format!("it is {}", client.status()),
+            });
+        }
+        for hold in client.holds() {
+            let what = match hold.reason {
+                HoldReason::Dispute => "the dispute of tx",
+                HoldReason::Authorization => "authorization",
+                HoldReason::Reserve => {
+                    notes.push(format!(
+                        "{} is held as a reserve carried over from before \
+                        the run",
+                        hold.amount
+                    ));
+                    continue;
+                }
+            };
+            let tx = hold.tx.unwrap_or_default();
+            let opened = match self.opened.get(&(tx, hold.reason)) {
+                Some(Some(line)) => format!(", opened on line {}", line),
+                _ => String::new(),
+            };
+            notes.push(format!(
+                "{} is held for {} {}{}",
+                hold.amount, what, tx, opened
+            ));
+        }
+        notes
+    }
+}
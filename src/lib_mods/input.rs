@@ -0,0 +1,438 @@
+use crate::{
+    binlog::{self, BinReader},
+    transaction::{self, ColumnMap, RawTransaction, Transaction, TxReader},
+};
+use std::{
+    convert::TryFrom,
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    result,
+    str::FromStr,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{open, CsvOptions, Error, InputFormat, JsonLinesReader};
+    use crate::transaction::{self, Transaction, TxReader};
+    use std::path::Path;
+
+    #[test]
+    fn test_input_format_detect() {
+        let detect = |path: &str| InputFormat::detect(Path::new(path));
+        assert_eq!(detect("a/b.csv"), InputFormat::Csv);
+        assert_eq!(detect("a/b.JSONL"), InputFormat::JsonLines);
+        assert_eq!(detect("b.ndjson"), InputFormat::JsonLines);
+        assert_eq!(detect("b.json"), InputFormat::Csv);
+        assert_eq!(detect("b.bin"), InputFormat::Binary);
+        assert_eq!(detect("b"), InputFormat::Csv);
+        assert_eq!("jsonl".parse(), Ok(InputFormat::JsonLines));
+        assert!("xml".parse::<InputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_json_lines_matches_csv() {
+        let csv: Vec<Transaction> = TxReader::from_path("./tests/test2.csv")
+            .unwrap()
+            .map(|tx| tx.unwrap())
+            .collect();
+        let json: Vec<Transaction> =
+            open("./tests/test2.jsonl", None, &Default::default())
+                .unwrap()
+                .map(|tx| tx.unwrap())
+                .collect();
+        assert_eq!(csv, json);
+    }
+
+    fn read_csv(data: &str, opts: &CsvOptions) -> Vec<Transaction> {
+        opts.tx_reader(data.as_bytes())
+            .unwrap()
+            .map(|tx| tx.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_csv_dialects() {
+        let expected = vec![
+            Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 1.0,
+                dispute: false,
+            },
+            Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: None,
+            },
+        ];
+
+        let padded = "\
+type, client, tx, amount
+deposit, 1, 1, 1.0
+dispute, 1, 1,
+";
+        let trim = CsvOptions {
+            trim: true,
+            ..Default::default()
+        };
+        assert_eq!(read_csv(padded, &trim), expected);
+
+        let tabs = "deposit\t1\t1\t1.0\ndispute\t1\t1\t\n";
+        let tsv = CsvOptions {
+            delimiter: b'\t',
+            has_headers: false,
+            ..Default::default()
+        };
+        assert_eq!(read_csv(tabs, &tsv), expected);
+
+        let reordered = "\
+amount,note,tx,type,client
+1.0,first,1,deposit,1
+,,1,dispute,1,unexpected
+";
+        let flexible = CsvOptions {
+            flexible: true,
+            ..Default::default()
+        };
+        assert_eq!(read_csv(reordered, &flexible), expected);
+        let strict = CsvOptions::default();
+        assert!(strict
+            .tx_reader(reordered.as_bytes())
+            .unwrap()
+            .any(|tx| tx.is_err()));
+
+        // named columns that are not mapped are ignored without --flexible
+        let extra = "\
+account,kind,note,id,value,currency
+1,deposit,first,1,1.0,EUR
+1,dispute,,1,,EUR
+";
+        let opts = CsvOptions {
+            columns: "type=kind,client=account,tx=id,amount=value"
+                .parse()
+                .unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(read_csv(extra, &opts), expected);
+
+        let mapped = "1;1.0;deposit;1\n1;;DISPUTE;1\n";
+        let opts = CsvOptions {
+            delimiter: b';',
+            has_headers: false,
+            columns: "client=0,amount=1,type=2,tx=3".parse().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(read_csv(mapped, &opts), expected);
+    }
+
+    #[test]
+    fn test_csv_errors_have_line_numbers() {
+        let path = std::env::temp_dir()
+            .join(format!("floof-input-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,x,2,1.0\n",
+        )
+        .unwrap();
+        let txs: Vec<_> =
+            open(&path, None, &CsvOptions::default()).unwrap().collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(txs[0].is_ok());
+        match &txs[1] {
+            Err(Error::Line {
+                line: 3,
+                source:
+                    transaction::Error::InvalidField {
+                        field: "client", ..
+                    },
+            }) => (),
+            res => panic!("expected invalid client on line 3, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_json_lines_errors_have_line_numbers() {
+        let data = "\
+{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 2.5}
+
+{\"type\": \"deposit\", \"client\": 1, \"tx\": 2}
+{\"type\": \"dispute\", \"client\": 1, \"tx\": 1, \"amount\": null}
+{\"type\": \"deposit\", \"client\": -1, \"tx\": 3, \"amount\": 1}
+";
+        let txs: Vec<_> = JsonLinesReader::new(data.as_bytes()).collect();
+        assert_eq!(txs.len(), 4);
+        assert!(txs[0].is_ok());
+        match &txs[1] {
+            Err(Error::Line {
+                line: 3,
+                source: transaction::Error::MissingAmount(..),
+            }) => (),
+            res => panic!("expected missing amount on line 3, got {:?}", res),
+        }
+        assert_eq!(
+            *txs[2].as_ref().unwrap(),
+            Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: None,
+            }
+        );
+        match &txs[3] {
+            Err(Error::Line {
+                line: 5,
+                source: transaction::Error::Json(_),
+            }) => (),
+            res => panic!("expected json error on line 5, got {:?}", res),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("line {line}: {source}")]
+    Line {
+        line: u64,
+        #[source]
+        source: transaction::Error,
+    },
+
+    #[error(transparent)]
+    Tx(#[from] transaction::Error),
+
+    #[error(transparent)]
+    Bin(#[from] binlog::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Csv,
+    // newline-delimited JSON objects with the same fields as the csv columns
+    JsonLines,
+    // see binlog
+    Binary,
+}
+
+impl InputFormat {
+    // Guess the format from the file extension, falling back to csv. `.json`
+    // is left to snapshots and stats
+    pub fn detect(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+
+        match ext.as_deref() {
+            Some("jsonl") | Some("ndjson") => InputFormat::JsonLines,
+            Some("bin") | Some("floof") => InputFormat::Binary,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" | "ndjson" | "json" => Ok(InputFormat::JsonLines),
+            "bin" | "binary" => Ok(InputFormat::Binary),
+            _ => Err(format!("unknown input format: {}", s)),
+        }
+    }
+}
+
+// Reads one RawTransaction per line. Blank lines are skipped
+#[derive(Debug)]
+pub struct JsonLinesReader<R> {
+    rdr: R,
+    buf: String,
+    line: u64,
+}
+
+impl<R: BufRead> JsonLinesReader<R> {
+    pub fn new(rdr: R) -> Self {
+        Self {
+            rdr,
+            buf: String::new(),
+            line: 0,
+        }
+    }
+
+    // Line of the row most recently read
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    fn parse(&self) -> transaction::Result<Transaction> {
+        let rtx: RawTransaction = serde_json::from_str(&self.buf)?;
+        Transaction::try_from(rtx)
+    }
+}
+
+impl<R: BufRead> Iterator for JsonLinesReader<R> {
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            match self.rdr.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(e) => return Some(Err(e.into())),
+            }
+
+            if !self.buf.trim().is_empty() {
+                return Some(self.parse().map_err(|source| Error::Line {
+                    line: self.line,
+                    source,
+                }));
+            }
+        }
+    }
+}
+
+// Attach the input line, when known, to an error for a row
+pub(crate) fn at_line(line: Option<u64>, source: transaction::Error) -> Error {
+    match line {
+        Some(line) => Error::Line { line, source },
+        None => source.into(),
+    }
+}
+
+// How to read csv input that does not follow the default dialect, such as
+// the padded `deposit, 1, 1, 1.0` style or tab separated exports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    // without headers the columns are taken by position, in the order
+    // type,client,tx,amount unless mapped otherwise
+    pub has_headers: bool,
+    // strip whitespace around headers and fields
+    pub trim: bool,
+    // accept rows with more or fewer fields than the header. Columns that
+    // are not mapped are ignored either way
+    pub flexible: bool,
+    pub columns: ColumnMap,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            trim: false,
+            flexible: false,
+            columns: ColumnMap::default(),
+        }
+    }
+}
+
+impl CsvOptions {
+    fn builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(self.flexible)
+            .trim(if self.trim {
+                csv::Trim::All
+            } else {
+                csv::Trim::None
+            });
+        builder
+    }
+
+    pub fn reader<R: io::Read>(&self, rdr: R) -> csv::Reader<R> {
+        self.builder().from_reader(rdr)
+    }
+
+    pub fn reader_from_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> transaction::Result<csv::Reader<File>> {
+        Ok(self.builder().from_path(path)?)
+    }
+
+    pub fn tx_reader<R: io::Read>(
+        &self,
+        rdr: R,
+    ) -> transaction::Result<TxReader<R>> {
+        TxReader::with_columns(self.reader(rdr), &self.columns)
+    }
+}
+
+// Transactions from an input file in any of the supported formats
+#[derive(Debug)]
+pub enum TxSource {
+    Csv(TxReader<File>),
+    JsonLines(JsonLinesReader<BufReader<File>>),
+    Binary(BinReader<BufReader<File>>),
+}
+
+impl Iterator for TxSource {
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            TxSource::Csv(rdr) => {
+                let res = rdr.next()?;
+                let line = rdr.line();
+                Some(res.map_err(|source| at_line(line, source)))
+            }
+            TxSource::JsonLines(rdr) => rdr.next(),
+            TxSource::Binary(rdr) => Some(rdr.next()?.map_err(Error::from)),
+        }
+    }
+}
+
+impl TxSource {
+    // Line of the row most recently read. Binary input has no lines
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            TxSource::Csv(rdr) => rdr.line(),
+            TxSource::JsonLines(rdr) => Some(rdr.line()),
+            TxSource::Binary(_) => None,
+        }
+    }
+
+    // Every row together with its line
+    pub fn numbered(
+        mut self,
+    ) -> impl Iterator<Item = (Option<u64>, Result<Transaction>)> {
+        std::iter::from_fn(move || {
+            let row = self.next()?;
+            Some((self.line(), row))
+        })
+    }
+}
+
+// Open `path` as `format`, or as whatever its extension suggests. `csv` only
+// applies to csv input
+pub fn open<P: AsRef<Path>>(
+    path: P,
+    format: Option<InputFormat>,
+    csv: &CsvOptions,
+) -> Result<TxSource> {
+    let path = path.as_ref();
+    match format.unwrap_or_else(|| InputFormat::detect(path)) {
+        InputFormat::Csv => Ok(TxSource::Csv(TxReader::with_columns(
+            csv.reader_from_path(path)?,
+            &csv.columns,
+        )?)),
+        InputFormat::JsonLines => Ok(TxSource::JsonLines(
+            JsonLinesReader::new(BufReader::new(File::open(path)?)),
+        )),
+        InputFormat::Binary => Ok(TxSource::Binary(BinReader::new(
+            BufReader::new(File::open(path)?),
+        )?)),
+    }
+}
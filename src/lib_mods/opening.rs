@@ -0,0 +1,128 @@
+use crate::{client::Client, store::ClientStore};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    result,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{read, seed, Error};
+    use crate::{
+        client::{Client, Status},
+        store::{ClientStore, VecStore},
+    };
+
+    #[test]
+    fn test_opening_balances() {
+        let data = "\
+client,available,held,total,locked,status
+1,10.0,2.5,12.5,false,active
+2,0.0,0.0,0.0,true,locked
+";
+        let mut clients = VecStore::new();
+        clients.insert(Client::new(1, 1.));
+        clients.insert(Client::new(3, 1.));
+        seed(&mut clients, read(data.as_bytes()).unwrap());
+
+        assert_eq!(clients.len(), 3);
+        assert_eq!(clients.get(1).unwrap().available(), 10.);
+        assert_eq!(clients.get(1).unwrap().held(), 2.5);
+        assert!(clients.get(2).unwrap().is_locked());
+        assert_eq!(clients.get(3).unwrap().available(), 1.);
+
+        // the status column is optional and follows `locked` when absent
+        let data = "\
+client,available,held,total,locked
+1,10.0,2.5,12.5,false
+2,0.0,0.0,0.0,true
+";
+        let opening = read(data.as_bytes()).unwrap();
+        assert_eq!(opening[0].status(), Status::Active);
+        assert_eq!(opening[1].status(), Status::Locked);
+    }
+
+    #[test]
+    fn test_opening_balances_rejects() {
+        let data = "\
+client,available,held,total,locked,status
+1,10.0,2.5,13.0,false,active
+";
+        match read(data.as_bytes()) {
+            Err(Error::Csv(_)) => (),
+            res => panic!("expected Error::Csv, got {:?}", res),
+        }
+
+        // a total that is too low is only debt when the file says so
+        let data = "\
+client,available,held,total,locked,status
+1,10.0,2.5,12.0,false,active
+";
+        match read(data.as_bytes()) {
+            Err(Error::Csv(_)) => (),
+            res => panic!("expected Error::Csv, got {:?}", res),
+        }
+        let data = "\
+client,available,held,total,locked,status,debt
+1,10.0,2.5,12.0,false,active,0.5
+2,1.0,0.0,1.0,false,active,
+";
+        let clients = read(data.as_bytes()).unwrap();
+        assert_eq!(clients[0].debt(), 0.5);
+        assert_eq!(clients[1].debt(), 0.);
+
+        let data = "\
+client,available,held,total,locked,status
+1,1.0,0.0,1.0,false,active
+1,2.0,0.0,2.0,false,active
+";
+        match read(data.as_bytes()) {
+            Err(Error::Duplicate(1)) => (),
+            res => panic!("expected Error::Duplicate, got {:?}", res),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("client {0} has more than one opening balance")]
+    Duplicate(u16),
+
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// Read opening balances in the format of the processor output: the
+// `client,available,held,total,locked` columns, with optional `status` and
+// `debt` columns. `total` must be available + held - debt, so that a typo
+// does not become money the client owes
+pub fn read<R: io::Read>(rdr: R) -> Result<Vec<Client>> {
+    let mut seen = HashSet::new();
+    let mut clients = Vec::new();
+    for client in csv::Reader::from_reader(rdr).deserialize() {
+        let client: Client = client?;
+        if !seen.insert(client.id()) {
+            return Err(Error::Duplicate(client.id()));
+        }
+        clients.push(client);
+    }
+    Ok(clients)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Client>> {
+    read(BufReader::new(File::open(path)?))
+}
+
+// Put the opening balances into `clients`, replacing any it already has
+pub fn seed<S: ClientStore>(clients: &mut S, opening: Vec<Client>) {
+    for client in opening {
+        clients.insert(client);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    input::{self, CsvOptions},
+    transaction::{ColumnMap, Columns, Transaction},
+};
+use csv::ByteRecord;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{for_each, Options};
+    use crate::{
+        input::at_line,
+        transaction::{ColumnMap, TxReader},
+    };
+    use std::{fmt::Write, io};
+
+    fn feed(rows: u32) -> String {
+        let mut data = String::from("type,client,tx,amount\n");
+        for tx in 1..=rows {
+            match tx % 7 {
+                0 => writeln!(data, "dispute,{},{},", tx % 5, tx - 1),
+                // rejected rows must come out in order too
+                3 => writeln!(data, "bogus,{},{},1", tx % 5, tx),
+                _ => writeln!(data, "deposit,{},{},{}.5", tx % 5, tx, tx),
+            }
+            .unwrap();
+        }
+        data
+    }
+
+    fn pipelined(data: String, opts: Options) -> Vec<String> {
+        let rdr = csv::Reader::from_reader(io::Cursor::new(data));
+        let mut out = Vec::new();
+        for_each(rdr, &ColumnMap::default(), opts, |tx| {
+            out.push(format!("{:?}", tx))
+        })
+        .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_pipeline_preserves_order() {
+        let data = feed(10_000);
+        let mut rdr =
+            TxReader::from_csv(csv::Reader::from_reader(data.as_bytes()))
+                .unwrap();
+        let mut sequential = Vec::new();
+        while let Some(tx) = rdr.next() {
+            let line = rdr.line();
+            sequential.push(format!("{:?}", tx.map_err(|e| at_line(line, e))));
+        }
+
+        for &(workers, batch_size, queue_depth) in
+            [(1, 1, 1), (4, 3, 2), (8, 1000, 4)].iter()
+        {
+            let opts = Options {
+                workers,
+                batch_size,
+                queue_depth,
+            };
+            assert_eq!(pipelined(data.clone(), opts), sequential);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_empty_input() {
+        let data = String::from("type,client,tx,amount\n");
+        assert!(pipelined(data, Options::default()).is_empty());
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // parser threads
+    pub workers: usize,
+    // records handed to a parser at a time
+    pub batch_size: usize,
+    // batches allowed to queue up between stages. Together with batch_size
+    // this bounds how much of the input is in memory at once
+    pub queue_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            workers: num_cpus::get(),
+            batch_size: 4096,
+            queue_depth: 16,
+        }
+    }
+}
+
+type Batch<T> = (u64, T);
+
+// A read error ends the input, so it travels in place of the records that
+// could not be read
+enum Input {
+    Records(Vec<ByteRecord>),
+    Failed(csv::Error),
+}
+
+type Parsed = Vec<input::Result<Transaction>>;
+
+fn read<R: io::Read>(
+    mut rdr: csv::Reader<R>,
+    batch_size: usize,
+    credits: Receiver<()>,
+    recycled: Receiver<Vec<ByteRecord>>,
+    tx: SyncSender<Batch<Input>>,
+) {
+    let mut seq = 0;
+    loop {
+        // wait until the processor has room for another batch. An error means
+        // it has gone away
+        if credits.recv().is_err() {
+            return;
+        }
+
+        // reuse the records of batches the parsers are done with so that
+        // reading does not allocate once the pipeline is warm
+        let mut records = recycled.try_recv().unwrap_or_default();
+        records.resize_with(batch_size, ByteRecord::new);
+
+        let mut len = 0;
+        let mut failed = None;
+        while len < batch_size {
+            match rdr.read_byte_record(&mut records[len]) {
+                Ok(true) => len += 1,
+                Ok(false) => break,
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+        records.truncate(len);
+
+        let done = len < batch_size || failed.is_some();
+        if len > 0 {
+            if tx.send((seq, Input::Records(records))).is_err() {
+                return;
+            }
+            seq += 1;
+        }
+        if let Some(e) = failed {
+            let _ = tx.send((seq, Input::Failed(e)));
+            return;
+        }
+        if done {
+            return;
+        }
+    }
+}
+
+fn parse(
+    columns: Columns,
+    rx: Arc<Mutex<Receiver<Batch<Input>>>>,
+    recycle: Sender<Vec<ByteRecord>>,
+    tx: SyncSender<Batch<Parsed>>,
+) {
+    loop {
+        // only hold the lock while waiting for the next batch, not while
+        // parsing it
+        let next = rx.lock().unwrap().recv();
+        let (seq, input) = match next {
+            Ok(batch) => batch,
+            Err(_) => return,
+        };
+
+        let parsed = match input {
+            Input::Records(records) => {
+                let parsed = records
+                    .iter()
+                    .map(|record| {
+                        columns.convert(record).map_err(|source| {
+                            let line = record.position().map(|pos| pos.line());
+                            input::at_line(line, source)
+                        })
+                    })
+                    .collect();
+                let _ = recycle.send(records);
+                parsed
+            }
+            Input::Failed(e) => {
+                let line = e.position().map(|pos| pos.line());
+                vec![Err(input::at_line(line, e.into()))]
+            }
+        };
+        if tx.send((seq, parsed)).is_err() {
+            return;
+        }
+    }
+}
+
+// Reads records on one thread, converts them into Transactions on a pool of
+// `opts.workers` threads, and hands them to `f` on the calling thread in
+// input order. `f` sees exactly what iterating an input::TxSource would yield,
+// so a single processor can apply the transactions to an Engine. Only a
+// failure to read the headers or resolve `columns` is returned directly
+pub fn for_each<R, F>(
+    mut rdr: csv::Reader<R>,
+    columns: &ColumnMap,
+    opts: Options,
+    mut f: F,
+) -> input::Result<()>
+where
+    R: io::Read + Send + 'static,
+    F: FnMut(input::Result<Transaction>),
+{
+    let columns = Columns::for_reader(&mut rdr, columns)?;
+    let depth = opts.queue_depth.max(1);
+    let workers = opts.workers.max(1);
+
+    // every batch read needs a credit, which the processor hands back once
+    // the batch has been applied. This caps the batches in memory no matter
+    // how far ahead of a slow batch the other workers get
+    let in_flight = 2 * depth + workers;
+    let (credit_tx, credit_rx) = mpsc::sync_channel(in_flight);
+    for _ in 0..in_flight {
+        credit_tx.send(()).unwrap();
+    }
+
+    let (input_tx, input_rx) = mpsc::sync_channel(depth);
+    let (parsed_tx, parsed_rx) = mpsc::sync_channel(depth);
+    let (recycle_tx, recycle_rx) = mpsc::channel();
+    let input_rx = Arc::new(Mutex::new(input_rx));
+
+    let batch_size = opts.batch_size.max(1);
+    let mut handles: Vec<JoinHandle<()>> = vec![thread::spawn(move || {
+        read(rdr, batch_size, credit_rx, recycle_rx, input_tx)
+    })];
+    for _ in 0..workers {
+        let input_rx = Arc::clone(&input_rx);
+        let recycle_tx = recycle_tx.clone();
+        let parsed_tx = parsed_tx.clone();
+        handles.push(thread::spawn(move || {
+            parse(columns, input_rx, recycle_tx, parsed_tx)
+        }));
+    }
+    drop(recycle_tx);
+    drop(parsed_tx);
+
+    // batches finish out of order. Park them until their turn comes
+    let mut pending: HashMap<u64, Parsed> = HashMap::new();
+    let mut next = 0;
+    for (seq, parsed) in parsed_rx {
+        pending.insert(seq, parsed);
+        while let Some(parsed) = pending.remove(&next) {
+            parsed.into_iter().for_each(&mut f);
+            let _ = credit_tx.send(());
+            next += 1;
+        }
+    }
+
+    for handle in handles {
+        if let Err(panic) = handle.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+    assert!(pending.is_empty(), "pipeline dropped a batch");
+    Ok(())
+}
+
+pub fn for_each_in_path<P, F>(
+    path: P,
+    csv: &CsvOptions,
+    opts: Options,
+    f: F,
+) -> input::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(input::Result<Transaction>),
+{
+    let rdr: csv::Reader<File> = csv.reader_from_path(path)?;
+    for_each(rdr, &csv.columns, opts, f)
+}
@@ -0,0 +1,294 @@
+use crate::client::{Client, Status};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, io};
+
+#[cfg(test)]
+mod tests {
+    use super::{read, reconcile, Balance, Break};
+    use crate::client::Status;
+
+    fn balance(
+        client: u16,
+        available: f64,
+        held: f64,
+        locked: bool,
+    ) -> Balance {
+        Balance {
+            client,
+            available,
+            held,
+            total: available + held,
+            locked,
+            status: None,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_breaks() {
+        let expected = vec![
+            balance(1, 10., 0., false),
+            balance(2, 5., 1., false),
+            balance(3, 1., 0., false),
+        ];
+        let actual = vec![
+            balance(4, 0., 0., false),
+            balance(2, 5.005, 1., true),
+            balance(1, 10.0000001, 0., false),
+        ];
+
+        let report = reconcile(&expected, &actual, 1e-6);
+        assert_eq!(report.clients, 4);
+        assert_eq!(
+            report.breaks,
+            vec![
+                Break::Mismatch {
+                    client: 2,
+                    field: "available",
+                    expected: String::from("5"),
+                    actual: String::from("5.005"),
+                },
+                Break::Mismatch {
+                    client: 2,
+                    field: "total",
+                    expected: String::from("6"),
+                    actual: String::from("6.005"),
+                },
+                Break::Mismatch {
+                    client: 2,
+                    field: "locked",
+                    expected: String::from("false"),
+                    actual: String::from("true"),
+                },
+                Break::Missing(3),
+                Break::Extra(4),
+            ]
+        );
+
+        // locked is compared exactly whatever the tolerance
+        let report = reconcile(&expected[..2], &actual[1..], 0.01);
+        assert_eq!(report.breaks.len(), 1);
+        assert_eq!(
+            report.breaks[0].to_string(),
+            "client 2: locked is true, expected false"
+        );
+
+        let report = reconcile(&expected[..1], &actual[2..], 0.);
+        assert!(!report.is_clean());
+        let report = reconcile(&expected[..1], &expected[..1], 0.);
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn test_reconcile_duplicates() {
+        let expected = vec![
+            balance(1, 1., 0., false),
+            balance(1, 2., 0., false),
+            balance(2, 1., 0., false),
+        ];
+        let actual = vec![
+            balance(1, 2., 0., false),
+            balance(2, 1., 0., false),
+            balance(2, 1., 0., false),
+        ];
+
+        let report = reconcile(&expected, &actual, 0.);
+        assert_eq!(
+            report.breaks,
+            vec![
+                Break::Duplicate {
+                    client: 1,
+                    side: "expected",
+                },
+                Break::Duplicate {
+                    client: 2,
+                    side: "actual",
+                },
+            ]
+        );
+        assert_eq!(
+            report.breaks[0].to_string(),
+            "client 1: more than one expected row"
+        );
+    }
+
+    #[test]
+    fn test_reconcile_read() {
+        let data = "\
+client,available,held,total,locked,status
+1,1.5,0.5,2.0,false,active
+";
+        let balances = read(data.as_bytes()).unwrap();
+        assert_eq!(
+            balances,
+            vec![Balance {
+                client: 1,
+                available: 1.5,
+                held: 0.5,
+                total: 2.,
+                locked: false,
+                status: Some(Status::Active),
+            }]
+        );
+    }
+}
+
+// A client's balances as written by the processor or expected by another
+// system. Further columns are ignored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    pub client: u16,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+    // older outputs and other systems have no status, so it is not
+    // reconciled
+    #[serde(default)]
+    pub status: Option<Status>,
+}
+
+impl From<&Client> for Balance {
+    fn from(client: &Client) -> Self {
+        Self {
+            client: client.id(),
+            available: client.available(),
+            held: client.held(),
+            total: client.total(),
+            locked: client.is_locked(),
+            status: Some(client.status()),
+        }
+    }
+}
+
+pub fn read<R: io::Read>(rdr: R) -> csv::Result<Vec<Balance>> {
+    csv::Reader::from_reader(rdr).deserialize().collect()
+}
+
+// Where actual balances do not agree with the expected ones
+#[derive(Debug, Clone, PartialEq)]
+pub enum Break {
+    // expected but not in the actual balances
+    Missing(u16),
+    // in the actual balances but not expected
+    Extra(u16),
+    // more than one row for the client in the `expected` or `actual`
+    // balances, which are not compared further
+    Duplicate {
+        client: u16,
+        side: &'static str,
+    },
+    Mismatch {
+        client: u16,
+        field: &'static str,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Break::Missing(client) => write!(f, "client {}: missing", client),
+            Break::Extra(client) => {
+                write!(f, "client {}: not expected", client)
+            }
+            Break::Duplicate { client, side } => {
+                write!(f, "client {}: more than one {} row", client, side)
+            }
+            Break::Mismatch {
+                client,
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {}: {} is {}, expected {}",
+                client, field, actual, expected
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    // clients in either set of balances
+    pub clients: usize,
+    // by client id
+    pub breaks: Vec<Break>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.breaks.is_empty()
+    }
+}
+
+// Match `actual` against `expected` by client id. Amounts that differ by no
+// more than `tolerance` agree; pass 0 to compare them exactly
+pub fn reconcile(
+    expected: &[Balance],
+    actual: &[Balance],
+    tolerance: f64,
+) -> Report {
+    let mut pairs: BTreeMap<u16, (Vec<&Balance>, Vec<&Balance>)> =
+        BTreeMap::new();
+    for balance in expected {
+        pairs.entry(balance.client).or_default().0.push(balance);
+    }
+    for balance in actual {
+        pairs.entry(balance.client).or_default().1.push(balance);
+    }
+
+    let mut report = Report {
+        clients: pairs.len(),
+        breaks: Vec::new(),
+    };
+    for (client, (expected, actual)) in pairs {
+        let sides = [("expected", &expected), ("actual", &actual)];
+        let duplicates: Vec<Break> = sides
+            .iter()
+            .filter(|(_, rows)| rows.len() > 1)
+            .map(|&(side, _)| Break::Duplicate { client, side })
+            .collect();
+        if !duplicates.is_empty() {
+            report.breaks.extend(duplicates);
+            continue;
+        }
+        let (expected, actual) = match (expected.first(), actual.first()) {
+            (Some(expected), Some(actual)) => (*expected, *actual),
+            (Some(_), None) => {
+                report.breaks.push(Break::Missing(client));
+                continue;
+            }
+            (None, _) => {
+                report.breaks.push(Break::Extra(client));
+                continue;
+            }
+        };
+
+        let amounts = [
+            ("available", expected.available, actual.available),
+            ("held", expected.held, actual.held),
+            ("total", expected.total, actual.total),
+        ];
+        for &(field, expected, actual) in amounts.iter() {
+            if (expected - actual).abs() > tolerance {
+                report.breaks.push(Break::Mismatch {
+                    client,
+                    field,
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+        if expected.locked != actual.locked {
+            report.breaks.push(Break::Mismatch {
+                client,
+                field: "locked",
+                expected: expected.locked.to_string(),
+                actual: actual.locked.to_string(),
+            });
+        }
+    }
+    report
+}
@@ -0,0 +1,223 @@
+use crate::{
+    client::{Client, Status},
+    engine::{self, Engine},
+    input,
+    store::ClientStore,
+    transaction::Transaction,
+};
+use serde::Serialize;
+use std::fmt;
+
+#[cfg(test)]
+mod tests {
+    use super::{replay, state_at, Step, Until};
+    use crate::{
+        client::{Client, Status},
+        engine::Engine,
+        input,
+        transaction::{self, Transaction},
+    };
+    use std::collections::HashMap;
+
+    fn rows() -> Vec<(Option<u64>, input::Result<Transaction>)> {
+        let deposit = |tx| Transaction::Deposit {
+            client: 1,
+            tx,
+            amount: 1.,
+            dispute: false,
+        };
+        vec![
+            (Some(2), Ok(deposit(1))),
+            (
+                Some(3),
+                Err(transaction::Error::MissingAmount("deposit", 2).into()),
+            ),
+            // a blank line 4
+            (Some(5), Ok(deposit(3))),
+            (Some(6), Ok(deposit(1))),
+            (Some(7), Ok(deposit(4))),
+        ]
+    }
+
+    fn replayed(until: Until) -> (Vec<Option<u64>>, bool) {
+        let mut lines = Vec::new();
+        let reached =
+            replay(rows(), until, |line, _| lines.push(line)).is_some();
+        (lines, reached)
+    }
+
+    #[test]
+    fn test_replay_until() {
+        assert_eq!(replayed(Until::Line(3)), (vec![Some(2), Some(3)], true));
+        assert_eq!(replayed(Until::Line(4)), (vec![Some(2), Some(3)], true));
+        assert_eq!(
+            replayed(Until::Tx(3)),
+            (vec![Some(2), Some(3), Some(5)], true)
+        );
+        // the first row with the id
+        assert_eq!(replayed(Until::Tx(1)), (vec![Some(2)], true));
+        assert!(!replayed(Until::Line(9)).1);
+        assert!(!replayed(Until::Tx(9)).1);
+
+        // rows without lines are counted instead
+        let rows = rows().into_iter().map(|(_, row)| (None, row));
+        let mut count = 0;
+        assert_eq!(replay(rows, Until::Line(2), |_, _| count += 1), Some(2));
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_state_at() {
+        let engine = Engine::new(HashMap::<u16, Client>::new());
+        let (engine, reached) = state_at(engine, rows(), Until::Tx(3));
+        assert!(reached);
+        assert_eq!(engine.client(1).unwrap().available(), 2.);
+    }
+
+    #[test]
+    fn test_step_display() {
+        let step = Step {
+            line: Some(4),
+            ty: "withdrawal",
+            tx: 2,
+            client: 1,
+            available: 1.5,
+            held: 0.,
+            total: 1.5,
+            status: Status::Frozen,
+            rejected: Some(String::from("withdrawal refused")),
+        };
+        assert_eq!(
+            step.to_string(),
+            "line 4: withdrawal 2, client 1 available 1.5 held 0 total 1.5 \
+            frozen (rejected: withdrawal refused)"
+        );
+    }
+}
+
+// Where a replay stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    // after the row on this input line. Rows of input without lines, such
+    // as the binary log, are counted from 1 instead
+    Line(u64),
+    // after the first row with this transaction id
+    Tx(u32),
+}
+
+impl fmt::Display for Until {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Until::Line(line) => write!(f, "line {}", line),
+            Until::Tx(tx) => write!(f, "tx {}", tx),
+        }
+    }
+}
+
+// Hand the rows to `apply` in order up to and including the one `until`
+// points at. Returns the line, or count, of the last row applied if that
+// point was reached
+pub fn replay<I, F>(rows: I, until: Until, mut apply: F) -> Option<u64>
+where
+    I: IntoIterator<Item = (Option<u64>, input::Result<Transaction>)>,
+    F: FnMut(Option<u64>, input::Result<Transaction>),
+{
+    let mut last = None;
+    for (count, (line, row)) in (1..).zip(rows) {
+        let at = line.unwrap_or(count);
+        // the line itself may be blank or unreadable
+        if matches!(until, Until::Line(n) if at > n) {
+            return last;
+        }
+        let id = row.as_ref().ok().map(Transaction::id);
+        apply(line, row);
+        last = Some(at);
+        match until {
+            Until::Line(n) if at == n => return last,
+            Until::Tx(tx) if id == Some(tx) => return last,
+            _ => (),
+        }
+    }
+    None
+}
+
+// `engine` as it was after the row `until` points at. Rows that cannot be
+// read or are rejected are skipped like in a normal run. The flag says
+// whether that row was reached at all
+pub fn state_at<S, I>(
+    mut engine: Engine<S>,
+    rows: I,
+    until: Until,
+) -> (Engine<S>, bool)
+where
+    S: ClientStore,
+    I: IntoIterator<Item = (Option<u64>, input::Result<Transaction>)>,
+{
+    let reached = replay(rows, until, |_, row| {
+        if let Ok(tx) = row {
+            let _ = engine.apply(tx);
+        }
+    });
+    (engine, reached.is_some())
+}
+
+// The state of a client after a transaction that named it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Step {
+    pub line: Option<u64>,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub tx: u32,
+    pub client: u16,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub status: Status,
+    pub rejected: Option<String>,
+}
+
+impl Step {
+    // `client` is None if the transaction was rejected before the client
+    // was created
+    pub fn new(
+        line: Option<u64>,
+        tx: &Transaction,
+        client: Option<&Client>,
+        res: &engine::Result<()>,
+    ) -> Self {
+        Self {
+            line,
+            ty: tx.ty(),
+            tx: tx.id(),
+            client: tx.client(),
+            available: client.map_or(0., Client::available),
+            held: client.map_or(0., Client::held),
+            total: client.map_or(0., Client::total),
+            status: client.map_or(Status::Active, Client::status),
+            rejected: res.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(
+            f,
+            "{} {}, client {} available {} held {} total {} {}",
+            self.ty,
+            self.tx,
+            self.client,
+            self.available,
+            self.held,
+            self.total,
+            self.status
+        )?;
+        if let Some(reason) = &self.rejected {
+            write!(f, " (rejected: {})", reason)?;
+        }
+        Ok(())
+    }
+}
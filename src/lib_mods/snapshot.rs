@@ -0,0 +1,289 @@
+use crate::{
+    client::{Client, Hold, Status},
+    engine::{Authorization, DisputeState, Engine},
+    store::ClientStore,
+    transaction::{self, RawTransaction, Transaction},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    result,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Snapshot};
+    use crate::{
+        client::Client,
+        engine::Engine,
+        transaction::Transaction,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut engine = Engine::new(HashMap::<u16, Client>::new());
+        let txs = vec![
+            Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 3.,
+                dispute: false,
+            },
+            Transaction::Deposit {
+                client: 2,
+                tx: 2,
+                amount: 4.,
+                dispute: false,
+            },
+            Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: Some(1.),
+            },
+            Transaction::Authorize {
+                client: 2,
+                tx: 3,
+                amount: 3.,
+            },
+        ];
+        for tx in txs {
+            engine.apply(tx).unwrap();
+        }
+
+        let json = serde_json::to_string(&Snapshot::capture(&engine)).unwrap();
+        let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, Snapshot::capture(&engine));
+
+        let mut restored =
+            snapshot.restore(HashMap::<u16, Client>::new()).unwrap();
+        assert_eq!(restored.client(1).unwrap().held(), 1.);
+        assert_eq!(restored.dispute(1).disputed, 1.);
+        assert_eq!(restored.client(2).unwrap().available(), 1.);
+        restored
+            .apply(Transaction::Capture {
+                client: 2,
+                tx: 3,
+                amount: Some(2.),
+            })
+            .unwrap();
+        assert_eq!(restored.client(2).unwrap().total(), 2.);
+        assert_eq!(restored.authorization(3).unwrap().remaining(), 1.);
+        restored
+            .apply(Transaction::Resolve {
+                client: 1,
+                tx: 1,
+                amount: None,
+            })
+            .unwrap();
+        assert!(restored
+            .apply(Transaction::Deposit {
+                client: 2,
+                tx: 2,
+                amount: 1.,
+                dispute: false
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_referring_tx() {
+        let json = r#"{
+            "clients": [],
+            "transactions": [
+                {"type": "dispute", "client": 1, "tx": 1, "disputed": false,
+                 "disputed_amount": 0, "held_amount": 0, "credit": 0,
+                 "charged_back": 0}
+            ],
+            "authorizations": []
+        }"#;
+        let snapshot: Snapshot = serde_json::from_str(json).unwrap();
+        match snapshot.restore(HashMap::<u16, Client>::new()) {
+            Err(Error::NotRecorded(1)) => (),
+            res => panic!("expected Error::NotRecorded, got {:?}", res.err()),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("tx {0} in snapshot is not a deposit or withdrawal")]
+    NotRecorded(u32),
+
+    #[error(transparent)]
+    Tx(#[from] transaction::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientState {
+    pub client: u16,
+    pub available: f64,
+    pub held: f64,
+    pub locked: bool,
+    pub status: Status,
+    pub debt: f64,
+    pub holds: Vec<Hold>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxState {
+    #[serde(flatten)]
+    pub tx: RawTransaction,
+    pub disputed: bool,
+    // amount under dispute
+    pub disputed_amount: f64,
+    // part of the disputed amount that is held
+    pub held_amount: f64,
+    // part of the held amount that was held on credit
+    pub credit: f64,
+    pub charged_back: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthState {
+    pub tx: u32,
+    #[serde(flatten)]
+    pub auth: Authorization,
+}
+
+// Everything an Engine needs to carry on where a run left off: client
+// balances, the deposits and withdrawals that can still be disputed and the
+// authorizations that can still be captured. Stored as json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub clients: Vec<ClientState>,
+    pub transactions: Vec<TxState>,
+    pub authorizations: Vec<AuthState>,
+}
+
+impl Snapshot {
+    // Clients and transactions are sorted by id so that the same state
+    // always gives the same snapshot
+    pub fn capture<S: ClientStore>(engine: &Engine<S>) -> Self {
+        let mut clients: Vec<ClientState> = engine
+            .clients()
+            .iter()
+            .map(|client| ClientState {
+                client: client.id(),
+                available: client.available(),
+                held: client.held(),
+                locked: client.is_locked(),
+                status: client.status(),
+                debt: client.debt(),
+                holds: client.holds().to_vec(),
+            })
+            .collect();
+        clients.sort_by_key(|client| client.client);
+
+        let mut transactions: Vec<TxState> = engine
+            .transactions()
+            .map(|tx| {
+                let state = engine.dispute(tx.id());
+                TxState {
+                    tx: RawTransaction::from(tx),
+                    disputed: state.is_open(),
+                    disputed_amount: state.disputed,
+                    held_amount: state.held,
+                    credit: state.credit,
+                    charged_back: state.charged_back,
+                }
+            })
+            .collect();
+        transactions.sort_by_key(|state| state.tx.tx);
+
+        let mut authorizations: Vec<AuthState> = engine
+            .authorizations()
+            .map(|(tx, auth)| AuthState { tx, auth: *auth })
+            .collect();
+        authorizations.sort_by_key(|state| state.tx);
+
+        Self {
+            clients,
+            transactions,
+            authorizations,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let rdr = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(rdr)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut wtr = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut wtr, self)?;
+        wtr.flush()?;
+        Ok(())
+    }
+
+    // Build an Engine from the snapshot, replacing any clients in `clients`
+    // that the snapshot also has
+    pub fn restore<S: ClientStore>(self, mut clients: S) -> Result<Engine<S>> {
+        let mut txs = Vec::with_capacity(self.transactions.len());
+        let mut disputes = Vec::new();
+        for state in self.transactions {
+            let id = state.tx.tx;
+            let tx = match Transaction::try_from(state.tx)? {
+                Transaction::Deposit {
+                    client, tx, amount, ..
+                } => Transaction::Deposit {
+                    client,
+                    tx,
+                    amount,
+                    dispute: state.disputed,
+                },
+                Transaction::Withdrawal {
+                    client, tx, amount, ..
+                } => Transaction::Withdrawal {
+                    client,
+                    tx,
+                    amount,
+                    dispute: state.disputed,
+                },
+                _ => return Err(Error::NotRecorded(id)),
+            };
+
+            let dispute = DisputeState {
+                disputed: state.disputed_amount,
+                held: state.held_amount,
+                credit: state.credit,
+                charged_back: state.charged_back,
+            };
+            txs.push(tx);
+            if dispute.disputed > 0. || dispute.charged_back > 0. {
+                disputes.push((id, dispute));
+            }
+        }
+
+        for state in self.clients {
+            let client = Client::from_parts(
+                state.client,
+                state.available,
+                state.held,
+                state.locked,
+            )
+            .with_holds(state.holds)
+            .with_debt(state.debt)
+            .with_status(state.status);
+            clients.insert(client);
+        }
+
+        let authorizations = self
+            .authorizations
+            .into_iter()
+            .map(|state| (state.tx, state.auth));
+        Ok(Engine::with_history(clients, txs, disputes)
+            .with_authorizations(authorizations))
+    }
+}
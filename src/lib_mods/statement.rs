@@ -0,0 +1,238 @@
+use crate::{
+    client::{Client, Status},
+    engine::{self, Authorization, Engine},
+    store::ClientStore,
+    transaction::Transaction,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, Entry};
+    use crate::{
+        client::{Client, Status},
+        engine::{Engine, Policy},
+        transaction::Transaction,
+    };
+    use float_cmp::approx_eq;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_statement_entries() {
+        let mut engine = Engine::new(HashMap::<u16, Client>::new());
+        let entries: Vec<Entry> = vec![
+            Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 3.,
+                dispute: false,
+            },
+            Transaction::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 5.,
+                dispute: false,
+            },
+            Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Deposit {
+                client: 1,
+                tx: 3,
+                amount: 1.,
+                dispute: false,
+            },
+        ]
+        .into_iter()
+        .flat_map(|tx| apply(&mut engine, tx))
+        .collect();
+
+        let changes: Vec<(f64, f64, f64)> = entries
+            .iter()
+            .map(|e| (e.available_change, e.held_change, e.total_change))
+            .collect();
+        let expected = [
+            (3., 0., 3.),
+            (0., 0., 0.),
+            (-3., 3., 0.),
+            (0., -3., -3.),
+            (0., 0., 0.),
+        ];
+        for (change, expected) in changes.iter().zip(expected.iter()) {
+            assert!(approx_eq!(f64, change.0, expected.0, ulps = 1));
+            assert!(approx_eq!(f64, change.1, expected.1, ulps = 1));
+            assert!(approx_eq!(f64, change.2, expected.2, ulps = 1));
+        }
+
+        assert_eq!(entries[0].rejected, None);
+        assert_eq!(
+            entries[1].rejected.as_deref(),
+            Some("client 1 has insufficient funds of 3")
+        );
+        assert!(approx_eq!(f64, entries[1].available, 3., ulps = 1));
+        assert!(entries[3].locked);
+        assert_eq!(entries[3].status, Status::Locked);
+        assert!(approx_eq!(f64, entries[3].total, 0., ulps = 1));
+        assert_eq!(entries[4].rejected.as_deref(), Some("client 1 is locked"));
+    }
+
+    #[test]
+    fn test_statement_expiry() {
+        let mut engine =
+            Engine::new(HashMap::<u16, Client>::new()).with_policy(Policy {
+                authorization_ttl: Some(1),
+                ..Policy::default()
+            });
+        let deposit = |client, tx, amount| Transaction::Deposit {
+            client,
+            tx,
+            amount,
+            dispute: false,
+        };
+        let entries: Vec<Entry> = vec![
+            deposit(1, 1, 10.),
+            Transaction::Authorize {
+                client: 1,
+                tx: 2,
+                amount: 2.,
+            },
+            deposit(1, 3, 5.),
+        ]
+        .into_iter()
+        .flat_map(|tx| apply(&mut engine, tx))
+        .collect();
+
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                let change = (e.available_change, e.held_change);
+                (e.tx, e.ty, change, (e.available, e.held))
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (1, "deposit", (10., 0.), (10., 0.)),
+                (2, "authorize", (-2., 2.), (8., 2.)),
+                // released before the deposit, which does not show it
+                (2, "expire", (2., -2.), (10., 0.)),
+                (3, "deposit", (5., 0.), (15., 0.)),
+            ]
+        );
+    }
+}
+
+// One line of a client's account statement: a transaction, what it did to
+// the client's balances and the balances after it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    pub client: u16,
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub amount: Option<f64>,
+    pub available_change: f64,
+    pub held_change: f64,
+    pub total_change: f64,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+    pub status: Status,
+    // why the engine refused the transaction. Rejected transactions leave
+    // the balances unchanged
+    pub rejected: Option<String>,
+}
+
+// available, held, total, locked and status
+type Balances = (f64, f64, f64, bool, Status);
+
+fn balances(client: Option<&Client>) -> Balances {
+    client.map_or((0., 0., 0., false, Status::Active), |c| {
+        (
+            c.available(),
+            c.held(),
+            c.total(),
+            c.is_locked(),
+            c.status(),
+        )
+    })
+}
+
+// Apply `tx` to `engine` and describe its effect on the client it names.
+// Authorizations that expire before it is processed come first, each in an
+// entry of its own with type "expire", so that the entry of `tx` only shows
+// what `tx` did
+pub fn apply<S: ClientStore>(
+    engine: &mut Engine<S>,
+    tx: Transaction,
+) -> Vec<Entry> {
+    let (client, id, ty, amount) = (tx.client(), tx.id(), tx.ty(), tx.amount());
+    let expiring: Vec<(u32, Authorization)> = engine
+        .expiring()
+        .map(|(tx, auth)| (tx, *auth))
+        .collect();
+    let mut before: HashMap<u16, Balances> = expiring
+        .iter()
+        .map(|(_, auth)| auth.client)
+        .chain(Some(client))
+        .map(|id| (id, balances(engine.client(id))))
+        .collect();
+    let res: engine::Result<()> = engine.apply(tx);
+
+    let mut entries = Vec::new();
+    for (auth_tx, auth) in expiring {
+        let expired = engine
+            .authorization(auth_tx)
+            .map_or(0., |now| now.expired - auth.expired);
+        if expired <= 0. {
+            continue;
+        }
+        // expiry only makes reserved funds available again
+        let state = before.get_mut(&auth.client).unwrap();
+        state.0 += expired;
+        state.1 -= expired;
+        entries.push(Entry {
+            client: auth.client,
+            tx: auth_tx,
+            ty: "expire",
+            amount: Some(expired),
+            available_change: expired,
+            held_change: -expired,
+            total_change: 0.,
+            available: state.0,
+            held: state.1,
+            total: state.2,
+            locked: state.3,
+            status: state.4,
+            rejected: None,
+        });
+    }
+
+    let before = before[&client];
+    let after = balances(engine.client(client));
+    entries.push(Entry {
+        client,
+        tx: id,
+        ty,
+        amount,
+        available_change: after.0 - before.0,
+        held_change: after.1 - before.1,
+        total_change: after.2 - before.2,
+        available: after.0,
+        held: after.1,
+        total: after.2,
+        locked: after.3,
+        status: after.4,
+        rejected: res.err().map(|e| e.to_string()),
+    });
+    entries
+}
@@ -0,0 +1,340 @@
+use crate::{
+    client::{Client, HoldReason, Status},
+    engine::{self, Engine},
+    store::ClientStore,
+    transaction::Transaction,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+    use crate::{
+        client::Client,
+        engine::Engine,
+        transaction::{AdminAction, Transaction},
+    };
+    use float_cmp::approx_eq;
+    use std::{collections::HashMap, time::Duration};
+
+    #[test]
+    fn test_stats_counters() {
+        let mut engine = Engine::new(HashMap::<u16, Client>::new());
+        let mut stats = Stats::default();
+        let txs = vec![
+            Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 3.,
+                dispute: false,
+            },
+            Transaction::Deposit {
+                client: 2,
+                tx: 2,
+                amount: 4.,
+                dispute: false,
+            },
+            Transaction::Withdrawal {
+                client: 2,
+                tx: 3,
+                amount: 1.,
+                dispute: false,
+            },
+            Transaction::Withdrawal {
+                client: 1,
+                tx: 4,
+                amount: 10.,
+                dispute: false,
+            },
+            Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Dispute {
+                client: 2,
+                tx: 3,
+                amount: None,
+            },
+            Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Dispute {
+                client: 3,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Authorize {
+                client: 2,
+                tx: 5,
+                amount: 2.,
+            },
+            Transaction::Capture {
+                client: 2,
+                tx: 5,
+                amount: Some(0.5),
+            },
+            // freezing or closing an account does not lock it
+            Transaction::Admin {
+                client: 2,
+                tx: 6,
+                action: AdminAction::Freeze,
+                actor: None,
+                reason: None,
+            },
+            Transaction::Deposit {
+                client: 4,
+                tx: 7,
+                amount: 1.,
+                dispute: false,
+            },
+            Transaction::Withdrawal {
+                client: 4,
+                tx: 8,
+                amount: 1.,
+                dispute: false,
+            },
+            Transaction::Admin {
+                client: 4,
+                tx: 9,
+                action: AdminAction::Close,
+                actor: None,
+                reason: None,
+            },
+        ];
+        for tx in txs {
+            let _ = stats.apply(&mut engine, tx);
+        }
+        stats.invalid();
+        stats.finish(&engine, Duration::from_millis(1500));
+
+        assert_eq!(stats.transactions["deposit"], 3);
+        assert_eq!(stats.transactions["withdrawal"], 3);
+        assert_eq!(stats.transactions["dispute"], 3);
+        assert_eq!(stats.transactions["chargeback"], 1);
+        assert_eq!(stats.accepted, 12);
+        assert_eq!(stats.rejected["insufficient_funds"], 1);
+        assert_eq!(stats.rejected["unknown_client"], 1);
+        assert_eq!(stats.invalid_rows, 1);
+        assert_eq!(stats.clients_created, 3);
+        assert_eq!(stats.clients_locked, 1);
+        assert_eq!(stats.open_disputes, 1);
+        assert!(approx_eq!(f64, stats.deposited, 8., ulps = 1));
+        // captures take funds like withdrawals
+        assert!(approx_eq!(f64, stats.withdrawn, 2.5, ulps = 1));
+        assert!(approx_eq!(f64, stats.charged_back, 3., ulps = 1));
+        assert!(approx_eq!(f64, stats.held, 1., ulps = 1));
+        assert!(approx_eq!(f64, stats.authorized, 2., ulps = 1));
+        assert!(approx_eq!(f64, stats.captured, 0.5, ulps = 1));
+        assert!(approx_eq!(f64, stats.reserved, 1.5, ulps = 1));
+        assert_eq!(stats.debtors, 0);
+        assert_eq!(stats.statuses["frozen"], 1);
+        assert_eq!(stats.statuses["locked"], 1);
+        assert_eq!(stats.statuses["closed"], 1);
+        assert!(approx_eq!(f64, stats.elapsed_secs, 1.5, ulps = 1));
+    }
+
+    #[test]
+    fn test_stats_held_and_charged_back() {
+        let mut clients = HashMap::new();
+        // a reserve carried over from opening balances
+        clients.insert(1, Client::from_parts(1, 10., 2., false));
+        let mut engine = Engine::new(clients);
+        let mut stats = Stats::default();
+        let txs = vec![
+            Transaction::Withdrawal {
+                client: 1,
+                tx: 1,
+                amount: 4.,
+                dispute: false,
+            },
+            Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: None,
+            },
+            // refunds the withdrawal, which leaves the total as it is
+            Transaction::Chargeback {
+                client: 1,
+                tx: 1,
+                amount: Some(1.),
+            },
+        ];
+        for tx in txs {
+            stats.apply(&mut engine, tx).unwrap();
+        }
+        stats.finish(&engine, Duration::from_millis(1));
+
+        assert!(approx_eq!(f64, stats.charged_back, 1., ulps = 1));
+        assert!(approx_eq!(f64, stats.held, 3., ulps = 1));
+        assert!(approx_eq!(f64, stats.reserved, 0., ulps = 1));
+    }
+}
+
+// Summary of a run, counted as transactions are applied
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    // transactions read, by type
+    pub transactions: BTreeMap<&'static str, u64>,
+    pub accepted: u64,
+    // transactions the engine refused, by engine::Error::kind
+    pub rejected: BTreeMap<&'static str, u64>,
+    // rows that could not be read as a transaction
+    pub invalid_rows: u64,
+    pub clients_created: u64,
+    // clients whose status changed to locked. Frozen and closed accounts
+    // are not counted
+    pub clients_locked: u64,
+    // disputes neither resolved nor charged back by the end of the run
+    pub open_disputes: u64,
+    pub deposited: f64,
+    // withdrawals and captures, which take funds the same way
+    pub withdrawn: f64,
+    // funds still held for disputes at the end of the run
+    pub held: f64,
+    pub charged_back: f64,
+    // authorizations and what became of them. `reserved` is what is still
+    // held for them at the end of the run
+    pub authorized: f64,
+    pub captured: f64,
+    pub voided: f64,
+    pub expired: f64,
+    pub reserved: f64,
+    // clients at the end of the run, by account status
+    pub statuses: BTreeMap<&'static str, u64>,
+    // clients owing money at the end of the run and how much they owe
+    pub debtors: u64,
+    pub debt: f64,
+    pub elapsed_secs: f64,
+}
+
+impl Stats {
+    // Apply `tx` to `engine`, counting the outcome
+    pub fn apply<S: ClientStore>(
+        &mut self,
+        engine: &mut Engine<S>,
+        tx: Transaction,
+    ) -> engine::Result<()> {
+        let (id, ty, amount) = (tx.client(), tx.ty(), tx.amount());
+        let tx_id = tx.id();
+        let charged_back = engine.dispute(tx_id).charged_back;
+        let captured = |engine: &Engine<S>| {
+            engine.authorization(tx_id).map_or(0., |auth| auth.captured)
+        };
+        let before_capture = captured(engine);
+        let before = engine.client(id).map(Client::status);
+        let res = engine.apply(tx);
+        let after = engine.client(id).map(Client::status);
+
+        *self.transactions.entry(ty).or_insert(0) += 1;
+        match &res {
+            Ok(()) => self.accepted += 1,
+            Err(e) => *self.rejected.entry(e.kind()).or_insert(0) += 1,
+        }
+
+        if before.is_none() && after.is_some() {
+            self.clients_created += 1;
+        }
+        let locked = |status: Option<Status>| status == Some(Status::Locked);
+        if !locked(before) && locked(after) {
+            self.clients_locked += 1;
+        }
+
+        match (ty, &res) {
+            ("deposit", Ok(())) => self.deposited += amount.unwrap(),
+            ("withdrawal", Ok(())) => self.withdrawn += amount.unwrap(),
+            // a capture without an amount takes what the authorization
+            // still reserves
+            ("capture", Ok(())) => {
+                self.withdrawn += captured(engine) - before_capture
+            }
+            // a failed chargeback may still have moved funds, so go by the
+            // dispute state rather than the result. The balance does not
+            // tell, as charging back a withdrawal leaves the total as it is
+            ("chargeback", _) => {
+                self.charged_back +=
+                    engine.dispute(tx_id).charged_back - charged_back
+            }
+            _ => (),
+        }
+        res
+    }
+
+    // Count a row that could not be read
+    pub fn invalid(&mut self) {
+        self.invalid_rows += 1;
+    }
+
+    // Take the end of run figures from `engine`
+    pub fn finish<S: ClientStore>(
+        &mut self,
+        engine: &Engine<S>,
+        elapsed: Duration,
+    ) {
+        self.open_disputes = engine.open_disputes() as u64;
+        for (_, auth) in engine.authorizations() {
+            self.authorized += auth.amount;
+            self.captured += auth.captured;
+            self.voided += auth.voided;
+            self.expired += auth.expired;
+            self.reserved += auth.remaining();
+        }
+        // only what disputes hold, not authorizations or reserves carried
+        // over from opening balances
+        self.held = engine
+            .clients()
+            .iter()
+            .flat_map(|client| client.holds())
+            .filter(|hold| hold.reason == HoldReason::Dispute)
+            .fold(0., |held, hold| held + hold.amount);
+        for client in engine.clients().iter() {
+            *self.statuses.entry(client.status().name()).or_insert(0) += 1;
+        }
+        for client in engine.clients().iter().filter(|c| c.debt() > 0.) {
+            self.debtors += 1;
+            self.debt += client.debt();
+        }
+        self.elapsed_secs = elapsed.as_secs_f64();
+    }
+
+    pub fn rejected_total(&self) -> u64 {
+        self.rejected.values().sum()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "transactions:")?;
+        for (ty, count) in &self.transactions {
+            writeln!(f, "  {:<22}{}", ty, count)?;
+        }
+        writeln!(f, "{:<24}{}", "accepted:", self.accepted)?;
+        writeln!(f, "{:<24}{}", "rejected:", self.rejected_total())?;
+        for (reason, count) in &self.rejected {
+            writeln!(f, "  {:<22}{}", reason, count)?;
+        }
+        writeln!(f, "{:<24}{}", "invalid rows:", self.invalid_rows)?;
+        writeln!(f, "{:<24}{}", "clients created:", self.clients_created)?;
+        writeln!(f, "{:<24}{}", "clients locked:", self.clients_locked)?;
+        writeln!(f, "{:<24}{}", "open disputes:", self.open_disputes)?;
+        writeln!(f, "{:<24}{}", "deposited:", self.deposited)?;
+        writeln!(f, "{:<24}{}", "withdrawn:", self.withdrawn)?;
+        writeln!(f, "{:<24}{}", "held:", self.held)?;
+        writeln!(f, "{:<24}{}", "charged back:", self.charged_back)?;
+        writeln!(f, "{:<24}{}", "authorized:", self.authorized)?;
+        writeln!(f, "  {:<22}{}", "captured", self.captured)?;
+        writeln!(f, "  {:<22}{}", "voided", self.voided)?;
+        writeln!(f, "  {:<22}{}", "expired", self.expired)?;
+        writeln!(f, "  {:<22}{}", "reserved", self.reserved)?;
+        writeln!(f, "statuses:")?;
+        for (status, count) in &self.statuses {
+            writeln!(f, "  {:<22}{}", status, count)?;
+        }
+        writeln!(f, "{:<24}{}", "debtors:", self.debtors)?;
+        writeln!(f, "{:<24}{}", "debt:", self.debt)?;
+        write!(f, "{:<24}{:.3}s", "elapsed:", self.elapsed_secs)
+    }
+}
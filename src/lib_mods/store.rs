@@ -0,0 +1,224 @@
+use crate::client::{Client, Status};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientStore, VecStore};
+    use crate::client::Client;
+
+    #[test]
+    fn test_vec_store_sparse_ids() {
+        let mut store = VecStore::new();
+        store.insert(Client::new(u16::MAX, 1.));
+        store.insert(Client::new(3, 2.));
+        assert_eq!(store.len(), 2);
+        assert!(store.get(4).is_none());
+
+        let ids: Vec<u16> = store.iter().map(|client| client.id()).collect();
+        assert_eq!(ids, vec![3, u16::MAX]);
+    }
+}
+
+// Storage for client accounts, keyed by client id. The engine only ever
+// talks to clients through this trait
+pub trait ClientStore {
+    fn get(&self, id: u16) -> Option<&Client>;
+
+    fn get_mut(&mut self, id: u16) -> Option<&mut Client>;
+
+    // Insert `client`, returning the client previously stored under the
+    // same id, if any
+    fn insert(&mut self, client: Client) -> Option<Client>;
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Client> + '_>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains(&self, id: u16) -> bool {
+        self.get(id).is_some()
+    }
+
+    // Get the client with `id`, creating it with no funds if it does not
+    // exist yet
+    fn get_or_create(&mut self, id: u16) -> &mut Client {
+        if !self.contains(id) {
+            self.insert(Client::new(id, 0.));
+        }
+        self.get_mut(id).expect("client was just inserted")
+    }
+
+    // Persist any pending changes. A no-op for in-memory stores
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ClientStore for HashMap<u16, Client> {
+    fn get(&self, id: u16) -> Option<&Client> {
+        HashMap::get(self, &id)
+    }
+
+    fn get_mut(&mut self, id: u16) -> Option<&mut Client> {
+        HashMap::get_mut(self, &id)
+    }
+
+    fn insert(&mut self, client: Client) -> Option<Client> {
+        HashMap::insert(self, client.id(), client)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Client> + '_> {
+        Box::new(self.values())
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+// Dense store indexed directly by client id. Trades up to 64k empty slots
+// for hash-free lookups, and iterates in client id order
+#[derive(Debug, Default)]
+pub struct VecStore {
+    clients: Vec<Option<Client>>,
+    len: usize,
+}
+
+impl VecStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ClientStore for VecStore {
+    fn get(&self, id: u16) -> Option<&Client> {
+        self.clients.get(id as usize).and_then(Option::as_ref)
+    }
+
+    fn get_mut(&mut self, id: u16) -> Option<&mut Client> {
+        self.clients.get_mut(id as usize).and_then(Option::as_mut)
+    }
+
+    fn insert(&mut self, client: Client) -> Option<Client> {
+        let idx = client.id() as usize;
+        if idx >= self.clients.len() {
+            self.clients.resize_with(idx + 1, || None);
+        }
+
+        let prev = self.clients[idx].replace(client);
+        if prev.is_none() {
+            self.len += 1;
+        }
+        prev
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Client> + '_> {
+        Box::new(self.clients.iter().flatten())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+// Store persisted to a csv file in the same format as the processor
+// output, plus a `debt` column. Clients are loaded on `open` and written
+// back on `flush`, so a store can carry balances and locks from one run to
+// the next
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    clients: HashMap<u16, Client>,
+}
+
+impl FileStore {
+    // Open the store at `path`. A missing file is treated as an empty store
+    // and is created on the first flush
+    pub fn open<P: AsRef<Path>>(path: P) -> csv::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut clients = HashMap::new();
+
+        if path.exists() {
+            let mut rdr = csv::Reader::from_path(&path)?;
+            for client in rdr.deserialize() {
+                let client: Client = client?;
+                clients.insert(client.id(), client);
+            }
+        }
+
+        Ok(Self { path, clients })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// A client as the file store writes it. The output leaves debt out, but
+// reading the client back needs it
+#[derive(Debug, Serialize)]
+struct Record {
+    client: u16,
+    available: f64,
+    held: f64,
+    total: f64,
+    locked: bool,
+    status: Status,
+    debt: f64,
+}
+
+impl From<&Client> for Record {
+    fn from(client: &Client) -> Self {
+        Self {
+            client: client.id(),
+            available: client.available(),
+            held: client.held(),
+            total: client.total(),
+            locked: client.is_locked(),
+            status: client.status(),
+            debt: client.debt(),
+        }
+    }
+}
+
+impl ClientStore for FileStore {
+    fn get(&self, id: u16) -> Option<&Client> {
+        self.clients.get(&id)
+    }
+
+    fn get_mut(&mut self, id: u16) -> Option<&mut Client> {
+        self.clients.get_mut(&id)
+    }
+
+    fn insert(&mut self, client: Client) -> Option<Client> {
+        self.clients.insert(client.id(), client)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Client> + '_> {
+        Box::new(self.clients.values())
+    }
+
+    fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut clients: Vec<&Client> = self.clients.values().collect();
+        clients.sort_by_key(|client| client.id());
+
+        let mut wtr = csv::Writer::from_writer(File::create(&self.path)?);
+        for client in clients {
+            wtr.serialize(Record::from(client))?;
+        }
+        wtr.flush()
+    }
+}
@@ -1,9 +1,15 @@
-use serde::Deserialize;
-use std::{convert::TryFrom, result};
+use csv::ByteRecord;
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom, fs::File, io, path::Path, result, str, str::FromStr,
+};
 
 #[cfg(test)]
 mod tests {
-    use super::RawTransaction;
+    use super::{
+        AdminAction, Column, ColumnMap, Error, RawReader, RawTransaction,
+        TxReader,
+    };
     use crate::transaction::Transaction;
     use float_cmp::approx_eq;
     use std::convert::TryFrom;
@@ -27,6 +33,8 @@ mod tests {
             client: 1,
             tx: 1,
             amount: Some(1.2345),
+            actor: None,
+            reason: None,
         };
         let rtx = Transaction::try_from(rtx).unwrap();
         match rtx {
@@ -39,6 +47,238 @@ mod tests {
             _ => panic!("failed to be a deposit transaction")
         }
     }
+
+    // rows that the fast path has to treat exactly like serde does: case,
+    // padding, empty amounts, hex ids, extra and reordered columns
+    const TRICKY: &str = "\
+amount,tx,type,client,memo
+1.5,1,DePoSiT,1,x
+,2,dispute,1,
+2.,3,withdrawal,0x1F,
+1,4,deposit , 2,
+1,5, deposit,2,
+,6,deposit,2,
+1,7,transfer,2,
+1,8,deposit,70000,
+ 1,9,deposit,2,
+1,10,withdrawal,2,
+,0x0B,Chargeback,3,
+";
+
+    fn serde_path(data: &str) -> Vec<Result<Transaction, String>> {
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        rdr.deserialize()
+            .map(|rtx| {
+                let rtx: RawTransaction =
+                    rtx.map_err(|e| format!("{:?}", e.kind()))?;
+                Transaction::try_from(rtx).map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+
+    fn fast_path(data: &str) -> Vec<Result<Transaction, String>> {
+        let rdr = csv::Reader::from_reader(data.as_bytes());
+        TxReader::from_csv(rdr)
+            .unwrap()
+            .map(|tx| tx.map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_fast_path_matches_serde() {
+        let serde = serde_path(TRICKY);
+        let fast = fast_path(TRICKY);
+        assert_eq!(serde.len(), fast.len());
+        for (line, (serde, fast)) in serde.iter().zip(fast.iter()).enumerate()
+        {
+            match (serde, fast) {
+                (Ok(serde), Ok(fast)) => assert_eq!(serde, fast),
+                (Err(_), Err(_)) => (),
+                _ => panic!(
+                    "row {} differs. serde: {:?}, fast: {:?}",
+                    line + 1,
+                    serde,
+                    fast
+                ),
+            }
+        }
+
+        assert_eq!(
+            fast[2],
+            Ok(Transaction::Withdrawal {
+                client: 31,
+                tx: 3,
+                amount: 2.,
+                dispute: false
+            })
+        );
+        assert_eq!(
+            fast[10],
+            Ok(Transaction::Chargeback {
+                client: 3,
+                tx: 11,
+                amount: None
+            })
+        );
+    }
+
+    #[test]
+    fn test_raw_reader() {
+        let raw = |rdr: csv::Reader<&[u8]>, map: &ColumnMap| {
+            RawReader::with_columns(rdr, map)
+                .unwrap()
+                .map(|rtx| {
+                    let rtx = rtx.map_err(|e| match e {
+                        Error::Csv(e) => format!("{:?}", e.kind()),
+                        e => e.to_string(),
+                    })?;
+                    Transaction::try_from(rtx).map_err(|e| e.to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let rdr = csv::Reader::from_reader(TRICKY.as_bytes());
+        assert_eq!(raw(rdr, &ColumnMap::default()), serde_path(TRICKY));
+
+        let data = "x;1;1.5;deposit;7\nx;1;;dispute;7\n";
+        let map = "type=3,client=4,tx=1,amount=2".parse().unwrap();
+        let builder = || {
+            let mut builder = csv::ReaderBuilder::new();
+            builder.has_headers(false).delimiter(b';');
+            builder
+        };
+        let fast: Vec<_> =
+            TxReader::with_columns(builder().from_reader(data.as_bytes()), &map)
+                .unwrap()
+                .map(|tx| tx.map_err(|e| e.to_string()))
+                .collect();
+        assert_eq!(raw(builder().from_reader(data.as_bytes()), &map), fast);
+        assert_eq!(
+            fast[1],
+            Ok(Transaction::Dispute {
+                client: 7,
+                tx: 1,
+                amount: None
+            })
+        );
+    }
+
+    #[test]
+    fn test_fast_path_test_csvs() {
+        for path in ["./tests/test1.csv", "./tests/test2.csv"].iter() {
+            let data = std::fs::read_to_string(path).unwrap();
+            assert_eq!(serde_path(&data), fast_path(&data));
+        }
+    }
+
+    #[test]
+    fn test_fast_path_no_headers() {
+        let data = "deposit,1,1,1.5\nresolve,1,1\n";
+        let rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(data.as_bytes());
+        let txs: Vec<Transaction> =
+            TxReader::from_csv(rdr).unwrap().map(|tx| tx.unwrap()).collect();
+        assert_eq!(
+            txs,
+            vec![
+                Transaction::Deposit {
+                    client: 1,
+                    tx: 1,
+                    amount: 1.5,
+                    dispute: false
+                },
+                Transaction::Resolve {
+                    client: 1,
+                    tx: 1,
+                    amount: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_admin_rows() {
+        let data = "\
+type,client,tx,amount,actor,reason
+deposit,1,1,2.5,,
+Freeze,1,2,,ops,\"fraud, probably\"
+unlock,1,3,,,
+close,1,4,1.0,ops,
+";
+        assert_eq!(serde_path(data), fast_path(data));
+        assert_eq!(
+            fast_path(data)[1],
+            Ok(Transaction::Admin {
+                client: 1,
+                tx: 2,
+                action: AdminAction::Freeze,
+                actor: Some(String::from("ops")),
+                reason: Some(String::from("fraud, probably")),
+            })
+        );
+        let rtx = RawTransaction::from(fast_path(data)[3].as_ref().unwrap());
+        assert_eq!(rtx.ty(), "close");
+        assert_eq!((rtx.amount, rtx.actor.as_deref()), (None, Some("ops")));
+
+        // without the columns there is no actor or reason
+        let data = "type,client,tx,amount\nunlock,1,3,\n";
+        assert_eq!(serde_path(data), fast_path(data));
+    }
+
+    #[test]
+    fn test_fast_path_missing_column() {
+        let rdr = csv::Reader::from_reader("type,client,amount\n".as_bytes());
+        match TxReader::from_csv(rdr).unwrap_err() {
+            Error::MissingColumn("tx") => (),
+            e => panic!("expected Error::MissingColumn, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_column_map() {
+        let map: ColumnMap = "type=kind, client=2,TX=id".parse().unwrap();
+        assert_eq!(map.ty, Some(Column::Name(String::from("kind"))));
+        assert_eq!(map.client, Some(Column::Index(2)));
+        assert_eq!(map.tx, Some(Column::Name(String::from("id"))));
+        assert_eq!(map.amount, None);
+        assert!("kind".parse::<ColumnMap>().is_err());
+        assert!("note=3".parse::<ColumnMap>().is_err());
+
+        let data = "id,kind,value,client\n7,deposit,1.5,3\n";
+        // unmapped fields are looked up by their default header name
+        let map = "type=kind,amount=value".parse().unwrap();
+        let rdr = csv::Reader::from_reader(data.as_bytes());
+        match TxReader::with_columns(rdr, &map).unwrap_err() {
+            Error::MissingColumn("tx") => (),
+            e => panic!("expected Error::MissingColumn, got {:?}", e),
+        }
+
+        let map = "type=kind,tx=id,amount=value".parse().unwrap();
+        let rdr = csv::Reader::from_reader(data.as_bytes());
+        let txs: Vec<Transaction> = TxReader::with_columns(rdr, &map)
+            .unwrap()
+            .map(|tx| tx.unwrap())
+            .collect();
+        assert_eq!(
+            txs,
+            vec![Transaction::Deposit {
+                client: 3,
+                tx: 7,
+                amount: 1.5,
+                dispute: false
+            }]
+        );
+
+        let rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(data.as_bytes());
+        match TxReader::with_columns(rdr, &map).unwrap_err() {
+            Error::InvalidColumnMap(_) => (),
+            e => panic!("expected Error::InvalidColumnMap, got {:?}", e),
+        }
+    }
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -47,15 +287,39 @@ pub type Result<T> = result::Result<T, Error>;
 pub enum Error {
     #[error("invalid transaction: {0}")]
     InvalidTx(String),
+
+    #[error("{0} tx {1} is missing an amount")]
+    MissingAmount(&'static str, u32),
+
+    #[error("invalid {field}: {value:?}")]
+    InvalidField { field: &'static str, value: String },
+
+    #[error("missing column: {0}")]
+    MissingColumn(&'static str),
+
+    #[error("invalid column mapping: {0}")]
+    InvalidColumnMap(String),
+
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct RawTransaction {
     #[serde(rename = "type")]
     ty: String,
     pub client: u16,
     pub tx: u32,
     pub amount: Option<f64>,
+    // who performed an administrative transaction and why. Ignored for
+    // every other type
+    #[serde(default)]
+    pub actor: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
 }
 
 impl RawTransaction {
@@ -64,50 +328,538 @@ impl RawTransaction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Transaction {
     Deposit { client: u16, tx: u32, amount: f64, dispute: bool },
     Withdrawal { client: u16, tx: u32, amount: f64, dispute: bool },
-    Dispute { client: u16, tx: u32 },
-    Resolve { client: u16, tx: u32 },
-    Chargeback { client: u16, tx: u32 },
+    // `amount` is the part of the referenced deposit or withdrawal to act
+    // on. Without it a dispute covers whatever is not disputed yet, and a
+    // resolve or chargeback settles everything under dispute
+    Dispute { client: u16, tx: u32, amount: Option<f64> },
+    Resolve { client: u16, tx: u32, amount: Option<f64> },
+    Chargeback { client: u16, tx: u32, amount: Option<f64> },
+    // Reserves `amount` until it is captured, voided or expires
+    Authorize { client: u16, tx: u32, amount: f64 },
+    // Takes all or part of what the authorization `tx` reserved as a
+    // withdrawal. Without an amount it takes whatever is left
+    Capture { client: u16, tx: u32, amount: Option<f64> },
+    // Releases whatever the authorization `tx` still reserves
+    Void { client: u16, tx: u32 },
+    // Changes the status of an account rather than its balances. `tx` only
+    // identifies the action in the audit trail
+    Admin {
+        client: u16,
+        tx: u32,
+        action: AdminAction,
+        actor: Option<String>,
+        reason: Option<String>,
+    },
 }
 
-impl TryFrom<RawTransaction> for Transaction {
-    type Error = Error;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    // lift a lock or a freeze
+    Unlock,
+    // stop withdrawals but keep taking deposits
+    Freeze,
+    // stop money moving in or out while the account is looked into
+    Review,
+    // stop everything for good. Only an account without funds can be closed
+    Close,
+}
 
-    fn try_from(rtx: RawTransaction) -> Result<Self> {
-        if rtx.ty() == "deposit" {
+impl AdminAction {
+    fn parse(ty: &[u8]) -> Option<Self> {
+        if ty.eq_ignore_ascii_case(b"unlock") {
+            Some(AdminAction::Unlock)
+        } else if ty.eq_ignore_ascii_case(b"freeze") {
+            Some(AdminAction::Freeze)
+        } else if ty.eq_ignore_ascii_case(b"review") {
+            Some(AdminAction::Review)
+        } else if ty.eq_ignore_ascii_case(b"close") {
+            Some(AdminAction::Close)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdminAction::Unlock => "unlock",
+            AdminAction::Freeze => "freeze",
+            AdminAction::Review => "review",
+            AdminAction::Close => "close",
+        }
+    }
+}
+
+impl Transaction {
+    // Shared by the serde and the fast path so that both accept exactly the
+    // same rows. `ty` is matched case-insensitively without allocating.
+    // `note` gives the actor and reason, and is only called for admin types
+    fn build<N>(
+        ty: &[u8],
+        client: u16,
+        tx: u32,
+        amount: Option<f64>,
+        note: N,
+    ) -> Result<Self>
+    where
+        N: FnOnce() -> (Option<String>, Option<String>),
+    {
+        if ty.eq_ignore_ascii_case(b"deposit") {
             Ok(Transaction::Deposit {
-                client: rtx.client,
-                tx: rtx.tx,
-                amount: rtx.amount.expect("deposit tx bad format"),
+                client,
+                tx,
+                amount: amount.ok_or(Error::MissingAmount("deposit", tx))?,
                 dispute: false,
             })
-        } else if rtx.ty() == "withdrawal" {
+        } else if ty.eq_ignore_ascii_case(b"withdrawal") {
             Ok(Transaction::Withdrawal {
-                client: rtx.client,
-                tx: rtx.tx,
-                amount: rtx.amount.expect("withdrawal tx bad format"),
+                client,
+                tx,
+                amount: amount
+                    .ok_or(Error::MissingAmount("withdrawal", tx))?,
                 dispute: false,
             })
-        } else if rtx.ty() == "dispute" {
-            Ok(Transaction::Dispute {
-                client: rtx.client,
-                tx: rtx.tx,
-            })
-        } else if rtx.ty() == "resolve" {
-            Ok(Transaction::Resolve {
-                client: rtx.client,
-                tx: rtx.tx,
+        } else if ty.eq_ignore_ascii_case(b"dispute") {
+            Ok(Transaction::Dispute { client, tx, amount })
+        } else if ty.eq_ignore_ascii_case(b"resolve") {
+            Ok(Transaction::Resolve { client, tx, amount })
+        } else if ty.eq_ignore_ascii_case(b"chargeback") {
+            Ok(Transaction::Chargeback { client, tx, amount })
+        } else if ty.eq_ignore_ascii_case(b"authorize") {
+            Ok(Transaction::Authorize {
+                client,
+                tx,
+                amount: amount.ok_or(Error::MissingAmount("authorize", tx))?,
             })
-        } else if rtx.ty() == "chargeback" {
-            Ok(Transaction::Chargeback {
-                client: rtx.client,
-                tx: rtx.tx,
+        } else if ty.eq_ignore_ascii_case(b"capture") {
+            Ok(Transaction::Capture { client, tx, amount })
+        } else if ty.eq_ignore_ascii_case(b"void") {
+            Ok(Transaction::Void { client, tx })
+        } else if let Some(action) = AdminAction::parse(ty) {
+            let (actor, reason) = note();
+            Ok(Transaction::Admin {
+                client,
+                tx,
+                action,
+                actor,
+                reason,
             })
         } else {
-            Err(Error::InvalidTx(rtx.ty()))
+            Err(Error::InvalidTx(
+                String::from_utf8_lossy(ty).to_ascii_lowercase(),
+            ))
+        }
+    }
+
+    // Name of the type as it appears in the type column
+    pub fn ty(&self) -> &'static str {
+        match self {
+            Transaction::Deposit { .. } => "deposit",
+            Transaction::Withdrawal { .. } => "withdrawal",
+            Transaction::Dispute { .. } => "dispute",
+            Transaction::Resolve { .. } => "resolve",
+            Transaction::Chargeback { .. } => "chargeback",
+            Transaction::Authorize { .. } => "authorize",
+            Transaction::Capture { .. } => "capture",
+            Transaction::Void { .. } => "void",
+            Transaction::Admin { action, .. } => action.name(),
+        }
+    }
+
+    pub fn client(&self) -> u16 {
+        match *self {
+            Transaction::Deposit { client, .. }
+            | Transaction::Withdrawal { client, .. }
+            | Transaction::Dispute { client, .. }
+            | Transaction::Resolve { client, .. }
+            | Transaction::Chargeback { client, .. }
+            | Transaction::Authorize { client, .. }
+            | Transaction::Capture { client, .. }
+            | Transaction::Void { client, .. }
+            | Transaction::Admin { client, .. } => client,
+        }
+    }
+
+    // The transaction's own id, or the id of the transaction it refers to
+    pub fn id(&self) -> u32 {
+        match *self {
+            Transaction::Deposit { tx, .. }
+            | Transaction::Withdrawal { tx, .. }
+            | Transaction::Dispute { tx, .. }
+            | Transaction::Resolve { tx, .. }
+            | Transaction::Chargeback { tx, .. }
+            | Transaction::Authorize { tx, .. }
+            | Transaction::Capture { tx, .. }
+            | Transaction::Void { tx, .. }
+            | Transaction::Admin { tx, .. } => tx,
+        }
+    }
+
+    pub fn amount(&self) -> Option<f64> {
+        match *self {
+            Transaction::Deposit { amount, .. }
+            | Transaction::Withdrawal { amount, .. }
+            | Transaction::Authorize { amount, .. } => Some(amount),
+            Transaction::Dispute { amount, .. }
+            | Transaction::Resolve { amount, .. }
+            | Transaction::Chargeback { amount, .. }
+            | Transaction::Capture { amount, .. } => amount,
+            Transaction::Void { .. } | Transaction::Admin { .. } => None,
+        }
+    }
+}
+
+impl From<&Transaction> for RawTransaction {
+    fn from(tx: &Transaction) -> Self {
+        let (actor, reason) = match tx {
+            Transaction::Admin { actor, reason, .. } => {
+                (actor.clone(), reason.clone())
+            }
+            _ => (None, None),
+        };
+        Self {
+            ty: String::from(tx.ty()),
+            client: tx.client(),
+            tx: tx.id(),
+            amount: tx.amount(),
+            actor,
+            reason,
+        }
+    }
+}
+
+impl TryFrom<RawTransaction> for Transaction {
+    type Error = Error;
+
+    fn try_from(rtx: RawTransaction) -> Result<Self> {
+        let (actor, reason) = (rtx.actor, rtx.reason);
+        Transaction::build(
+            rtx.ty.as_bytes(),
+            rtx.client,
+            rtx.tx,
+            rtx.amount,
+            || (actor, reason),
+        )
+    }
+}
+
+// Where to find one RawTransaction field in a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Name(String),
+    Index(usize),
+}
+
+// Explicit column mapping for inputs that do not use the standard
+// type,client,tx,amount,actor,reason header. Unmapped fields fall back to
+// their standard header name, or to their standard position when there are
+// no headers. Parsed from e.g. "type=kind,client=customer,tx=2"
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnMap {
+    pub ty: Option<Column>,
+    pub client: Option<Column>,
+    pub tx: Option<Column>,
+    pub amount: Option<Column>,
+    pub actor: Option<Column>,
+    pub reason: Option<Column>,
+}
+
+impl FromStr for ColumnMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let mut map = ColumnMap::default();
+        for entry in s.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let mut kv = entry.splitn(2, '=');
+            let (field, col) = match (kv.next(), kv.next()) {
+                (Some(field), Some(col)) => (field.trim(), col.trim()),
+                _ => return Err(format!("expected field=column: {}", entry)),
+            };
+
+            let col = match col.parse() {
+                Ok(idx) => Column::Index(idx),
+                Err(_) => Column::Name(String::from(col)),
+            };
+            let slot = match field.to_ascii_lowercase().as_str() {
+                "type" => &mut map.ty,
+                "client" => &mut map.client,
+                "tx" => &mut map.tx,
+                "amount" => &mut map.amount,
+                "actor" => &mut map.actor,
+                "reason" => &mut map.reason,
+                _ => return Err(format!("unknown field: {}", field)),
+            };
+            *slot = Some(col);
+        }
+        Ok(map)
+    }
+}
+
+// Column positions of the RawTransaction fields within a record
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Columns {
+    ty: usize,
+    client: usize,
+    tx: usize,
+    amount: Option<usize>,
+    actor: Option<usize>,
+    reason: Option<usize>,
+}
+
+impl Columns {
+    pub(crate) fn for_reader<R: io::Read>(
+        rdr: &mut csv::Reader<R>,
+        map: &ColumnMap,
+    ) -> Result<Self> {
+        let headers = if rdr.has_headers() {
+            Some(rdr.byte_headers()?)
+        } else {
+            None
+        };
+
+        let find = |name: &'static str,
+                    pos: usize,
+                    col: &Option<Column>|
+         -> Result<Option<usize>> {
+            match (col, headers) {
+                (Some(Column::Index(idx)), _) => Ok(Some(*idx)),
+                (Some(Column::Name(header)), Some(headers)) => headers
+                    .iter()
+                    .position(|h| h == header.as_bytes())
+                    .map(Some)
+                    .ok_or(Error::MissingColumn(name)),
+                (Some(Column::Name(header)), None) => {
+                    Err(Error::InvalidColumnMap(format!(
+                        "{} is mapped to header {:?} but the input has no \
+                        headers",
+                        name, header
+                    )))
+                }
+                (None, Some(headers)) => Ok(headers
+                    .iter()
+                    .position(|h| h == name.as_bytes())),
+                // without headers serde maps fields by position
+                (None, None) => Ok(Some(pos)),
+            }
+        };
+        let require = |name: &'static str, pos: usize, col: &Option<Column>| {
+            find(name, pos, col)?.ok_or(Error::MissingColumn(name))
+        };
+
+        let mut columns = Self {
+            ty: require("type", 0, &map.ty)?,
+            client: require("client", 1, &map.client)?,
+            tx: require("tx", 2, &map.tx)?,
+            amount: find("amount", 3, &map.amount)?,
+            actor: find("actor", 4, &map.actor)?,
+            reason: find("reason", 5, &map.reason)?,
+        };
+        // the standard positions of the optional fields may have been
+        // mapped to something else
+        let taken = [
+            Some(columns.ty),
+            Some(columns.client),
+            Some(columns.tx),
+            columns.amount,
+        ];
+        if map.actor.is_none() && taken.contains(&columns.actor) {
+            columns.actor = None;
+        }
+        if map.reason.is_none() && taken.contains(&columns.reason) {
+            columns.reason = None;
+        }
+        Ok(columns)
+    }
+
+    // A header record naming each mapped column after its RawTransaction
+    // field, so that serde can read records with any column layout
+    fn headers(&self) -> ByteRecord {
+        let mut names = vec![&b""[..]; 4];
+        let mut name = |idx: usize, field: &'static [u8]| {
+            if idx >= names.len() {
+                names.resize(idx + 1, b"");
+            }
+            names[idx] = field;
+        };
+        name(self.ty, b"type");
+        name(self.client, b"client");
+        name(self.tx, b"tx");
+        if let Some(idx) = self.amount {
+            name(idx, b"amount");
+        }
+        if let Some(idx) = self.actor {
+            name(idx, b"actor");
+        }
+        if let Some(idx) = self.reason {
+            name(idx, b"reason");
+        }
+        ByteRecord::from(names)
+    }
+
+    pub(crate) fn convert(&self, rec: &ByteRecord) -> Result<Transaction> {
+        // the serde path decodes the whole record as utf-8 up front
+        if str::from_utf8(rec.as_slice()).is_err() {
+            return Err(invalid("record", rec.as_slice()));
+        }
+
+        let ty = field(rec, self.ty, "type")?;
+        let client = parse_u16(field(rec, self.client, "client")?, "client")?;
+        let tx = parse_u32(field(rec, self.tx, "tx")?, "tx")?;
+        let amount = parse_amount(self.amount.and_then(|idx| rec.get(idx)))?;
+
+        Transaction::build(ty, client, tx, amount, || {
+            (text(rec, self.actor), text(rec, self.reason))
+        })
+    }
+}
+
+fn field<'r>(
+    record: &'r ByteRecord,
+    idx: usize,
+    name: &'static str,
+) -> Result<&'r [u8]> {
+    record.get(idx).ok_or(Error::MissingColumn(name))
+}
+
+// An optional text field, already checked to be utf-8. Empty means missing,
+// as it does for serde
+fn text(record: &ByteRecord, idx: Option<usize>) -> Option<String> {
+    idx.and_then(|idx| record.get(idx))
+        .filter(|raw| !raw.is_empty())
+        .map(|raw| String::from_utf8_lossy(raw).into_owned())
+}
+
+fn invalid(name: &'static str, raw: &[u8]) -> Error {
+    Error::InvalidField {
+        field: name,
+        value: String::from_utf8_lossy(raw).into_owned(),
+    }
+}
+
+// Mirrors the csv deserializer: integers may be hex with a 0x prefix
+macro_rules! parse_int {
+    ($fn:ident, $ty:ty) => {
+        fn $fn(raw: &[u8], name: &'static str) -> Result<$ty> {
+            let s = str::from_utf8(raw).map_err(|_| invalid(name, raw))?;
+            let num = if let Some(hex) = s.strip_prefix("0x") {
+                <$ty>::from_str_radix(hex, 16)
+            } else {
+                s.parse()
+            };
+            num.map_err(|_| invalid(name, raw))
+        }
+    };
+}
+
+parse_int!(parse_u16, u16);
+parse_int!(parse_u32, u32);
+
+fn parse_amount(raw: Option<&[u8]>) -> Result<Option<f64>> {
+    match raw {
+        None => Ok(None),
+        Some(b"") => Ok(None),
+        Some(raw) => str::from_utf8(raw)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Some)
+            .ok_or_else(|| invalid("amount", raw)),
+    }
+}
+
+// Fast path that reads rows into a single reused ByteRecord and converts
+// them straight into Transactions, skipping RawTransaction and its String.
+// Accepts and rejects exactly the same rows as deserializing RawTransaction
+// and calling Transaction::try_from
+#[derive(Debug)]
+pub struct TxReader<R> {
+    rdr: csv::Reader<R>,
+    record: ByteRecord,
+    columns: Columns,
+}
+
+impl TxReader<File> {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_csv(csv::Reader::from_path(path)?)
+    }
+}
+
+impl<R: io::Read> TxReader<R> {
+    pub fn from_csv(rdr: csv::Reader<R>) -> Result<Self> {
+        Self::with_columns(rdr, &ColumnMap::default())
+    }
+
+    pub fn with_columns(
+        mut rdr: csv::Reader<R>,
+        map: &ColumnMap,
+    ) -> Result<Self> {
+        let columns = Columns::for_reader(&mut rdr, map)?;
+        Ok(Self {
+            rdr,
+            record: ByteRecord::new(),
+            columns,
+        })
+    }
+
+    // Line of the record most recently read
+    pub fn line(&self) -> Option<u64> {
+        self.record.position().map(csv::Position::line)
+    }
+}
+
+// Reads RawTransactions through serde rather than the fast path, with the
+// same column mapping as TxReader
+pub struct RawReader<R> {
+    rdr: csv::Reader<R>,
+    record: ByteRecord,
+    headers: ByteRecord,
+}
+
+impl<R: io::Read> RawReader<R> {
+    pub fn with_columns(
+        mut rdr: csv::Reader<R>,
+        map: &ColumnMap,
+    ) -> Result<Self> {
+        let headers = Columns::for_reader(&mut rdr, map)?.headers();
+        Ok(Self {
+            rdr,
+            record: ByteRecord::new(),
+            headers,
+        })
+    }
+
+    // Line of the record most recently read
+    pub fn line(&self) -> Option<u64> {
+        self.record.position().map(csv::Position::line)
+    }
+}
+
+impl<R: io::Read> Iterator for RawReader<R> {
+    type Item = Result<RawTransaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rdr.read_byte_record(&mut self.record) {
+            Ok(true) => Some(
+                self.record
+                    .deserialize(Some(&self.headers))
+                    .map_err(Error::from),
+            ),
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+impl<R: io::Read> Iterator for TxReader<R> {
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rdr.read_byte_record(&mut self.record) {
+            Ok(true) => Some(self.columns.convert(&self.record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
         }
     }
 }
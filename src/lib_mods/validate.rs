@@ -0,0 +1,141 @@
+use crate::{engine::Engine, store::ClientStore, transaction::Transaction};
+use serde::Serialize;
+use std::fmt;
+
+#[cfg(test)]
+mod tests {
+    use super::{Problem, Validator};
+    use crate::{client::Client, engine::Engine, transaction::Transaction};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_validator_reports_every_problem() {
+        let mut validator =
+            Validator::new(Engine::new(HashMap::<u16, Client>::new()));
+        validator.check(
+            Some(2),
+            Ok::<_, String>(Transaction::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 1.,
+                dispute: false,
+            }),
+        );
+        validator.check(Some(3), Err("invalid transaction: transfer"));
+        validator.check(
+            Some(4),
+            Ok::<_, String>(Transaction::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 2.,
+                dispute: false,
+            }),
+        );
+        validator.check(
+            None,
+            Ok::<_, String>(Transaction::Dispute {
+                client: 1,
+                tx: 1,
+                amount: None,
+            }),
+        );
+
+        let report = validator.into_report();
+        assert_eq!(report.rows, 4);
+        assert_eq!(
+            report.problems,
+            vec![
+                Problem {
+                    line: Some(3),
+                    message: String::from("invalid transaction: transfer"),
+                },
+                Problem {
+                    line: Some(4),
+                    message: String::from(
+                        "client 1 has insufficient funds of 1"
+                    ),
+                },
+            ]
+        );
+        assert_eq!(report.error_rate(), 0.5);
+        assert_eq!(
+            report.problems[0].to_string(),
+            "line 3: invalid \
+            transaction: transfer"
+        );
+    }
+}
+
+// A row that would not process cleanly
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    // line of the row, when the input has lines
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub rows: u64,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    // Share of rows with a problem
+    pub fn error_rate(&self) -> f64 {
+        if self.rows == 0 {
+            0.
+        } else {
+            self.problems.len() as f64 / self.rows as f64
+        }
+    }
+}
+
+// Simulates a run without producing balances. Every row is applied to a
+// scratch engine so that rows which parse but would be rejected, such as a
+// dispute of an unknown tx, are reported too
+#[derive(Debug)]
+pub struct Validator<S> {
+    engine: Engine<S>,
+    report: Report,
+}
+
+impl<S: ClientStore> Validator<S> {
+    // `engine` holds the state to validate against, e.g. one restored from a
+    // snapshot
+    pub fn new(engine: Engine<S>) -> Self {
+        Self {
+            engine,
+            report: Report::default(),
+        }
+    }
+
+    pub fn check<E: fmt::Display>(
+        &mut self,
+        line: Option<u64>,
+        row: Result<Transaction, E>,
+    ) {
+        self.report.rows += 1;
+        let message = match row {
+            Ok(tx) => match self.engine.apply(tx) {
+                Ok(()) => return,
+                Err(e) => e.to_string(),
+            },
+            Err(e) => e.to_string(),
+        };
+        self.report.problems.push(Problem { line, message });
+    }
+
+    pub fn into_report(self) -> Report {
+        self.report
+    }
+}
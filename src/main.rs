@@ -1,7 +1,28 @@
-use std::{collections::HashMap, convert::TryFrom, io};
-use floof::{self, transaction::{RawTransaction, Transaction}, client::Client};
-use args::Args;
-use structopt::StructOpt;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+    process,
+    time::Instant,
+};
+use floof::{
+    self,
+    client::Client,
+    debtors,
+    cdc::ChangeLog,
+    engine::Engine,
+    events::EventLog,
+    input::{self, InputFormat},
+    opening, pipeline,
+    replay::{self, Step, Until},
+    snapshot::Snapshot,
+    stats::Stats,
+    store::{ClientStore, FileStore, VecStore},
+    transaction::Transaction,
+};
+use args::{Args, Command, StoreKind};
+use structopt::{clap, StructOpt};
 use log::warn;
 
 pub mod bin_mods;
@@ -15,477 +36,347 @@ fn init_logging(verbose: bool) {
     }
 }
 
+fn run<S: ClientStore>(args: &Args, tx_path: &Path, mut clients: S) {
+    let start = Instant::now();
+    if let Some(path) = &args.opening_balances {
+        match opening::load(path) {
+            Ok(opening) => opening::seed(&mut clients, opening),
+            Err(e) => {
+                eprintln!("error: {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+    let mut engine = match &args.snapshot {
+        Some(path) => {
+            match Snapshot::load(path).and_then(|snap| snap.restore(clients)) {
+                Ok(engine) => engine,
+                Err(e) => {
+                    eprintln!("error: {}: {}", path.display(), e);
+                    process::exit(1);
+                }
+            }
+        }
+        None => Engine::new(clients),
+    }
+    .with_policy(args.policy.policy());
+    if let Some(path) = &args.events {
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("error: {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        };
+        engine.subscribe(Box::new(EventLog::new(BufWriter::new(file))));
+    }
+    if args.cdc {
+        engine.subscribe(Box::new(ChangeLog::new(io::stdout())));
+    }
+    let mut stats = Stats::default();
+
+    // rows that cannot be read are reported and skipped like transactions
+    // the engine rejects
+    let mut apply = |line: Option<u64>, tx_entry: input::Result<Transaction>| {
+        match tx_entry {
+            Ok(tx_entry) => {
+                let step = args
+                    .step
+                    .filter(|&client| client == tx_entry.client())
+                    .map(|_| tx_entry.clone());
+                let res = stats.apply(&mut engine, tx_entry);
+                if let Some(tx) = step {
+                    let client = engine.client(tx.client());
+                    eprintln!("{}", Step::new(line, &tx, client, &res));
+                }
+                if let Err(e) = res {
+                    warn!("{}", e);
+                }
+            }
+            Err(e) => {
+                warn!("{}", e);
+                stats.invalid();
+            }
+        }
+    };
+    let until = match (args.until_line, args.until_tx) {
+        (Some(line), _) => Some(Until::Line(line)),
+        (None, Some(tx)) => Some(Until::Tx(tx)),
+        (None, None) => None,
+    };
+
+    let csv = args.csv.options();
+    let format = args
+        .input_format
+        .unwrap_or_else(|| InputFormat::detect(tx_path));
+
+    if args.pipeline {
+        if format != InputFormat::Csv {
+            eprintln!("error: --pipeline only supports csv input");
+            process::exit(2);
+        }
+        if until.is_some() {
+            eprintln!(
+                "error: --pipeline cannot stop at --until-line or --until-tx"
+            );
+            process::exit(2);
+        }
+
+        let mut opts = pipeline::Options::default();
+        if let Some(workers) = args.workers {
+            opts.workers = workers;
+        }
+        pipeline::for_each_in_path(tx_path, &csv, opts, |tx| apply(None, tx))
+            .unwrap();
+    } else {
+        let rows = match input::open(tx_path, Some(format), &csv) {
+            Ok(rows) => rows.numbered(),
+            Err(e) => {
+                eprintln!("error: {}: {}", tx_path.display(), e);
+                process::exit(1);
+            }
+        };
+        match until {
+            Some(until) => {
+                if replay::replay(rows, until, &mut apply).is_none() {
+                    eprintln!("warning: input ended before {}", until);
+                }
+            }
+            None => rows.for_each(|(line, tx)| apply(line, tx)),
+        }
+    }
+    engine.flush_events().unwrap();
+    stats.finish(&engine, start.elapsed());
+    if let Some(path) = &args.save_snapshot {
+        Snapshot::capture(&engine).save(path).unwrap();
+    }
+    if let Some(path) = &args.debtors {
+        let mut wtr = csv::Writer::from_path(path).unwrap();
+        for debtor in debtors::report(engine.clients()) {
+            wtr.serialize(debtor).unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+    if let Some(path) = &args.audit {
+        let mut wtr = csv::Writer::from_path(path).unwrap();
+        for entry in engine.audit() {
+            wtr.serialize(entry).unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+
+    // with --cdc the changes were written as they happened
+    let mut clients = engine.into_clients();
+    if !args.cdc {
+        let mut wtr = csv::Writer::from_writer(io::stdout());
+        for client in clients.iter() {
+            wtr.serialize(client).unwrap();
+        }
+    }
+    clients.flush().unwrap();
+
+    if args.stats {
+        eprintln!("{}", stats);
+    }
+    if let Some(path) = &args.stats_json {
+        let file = File::create(path).unwrap();
+        serde_json::to_writer_pretty(file, &stats).unwrap();
+    }
+}
+
 fn main() {
     let args = Args::from_args();
     init_logging(args.verbose);
 
-    let mut client_map = HashMap::new();
-    let mut tx_map = HashMap::new();
-
-    let mut rdr = csv::Reader::from_path(args.tx_csv).unwrap();
-    for raw_tx in rdr.deserialize() {
-        let raw_tx: RawTransaction = raw_tx.unwrap();
-        let tx_entry = Transaction::try_from(raw_tx).unwrap();
-
-        match tx_entry {
-            Transaction::Deposit {
-                client,
-                tx,
-                amount,
-                dispute: _,
+    if let Some(cmd) = &args.cmd {
+        match cmd {
+            Command::Convert {
+                input,
+                output,
+                from,
+                to,
+                csv,
             } => {
-                let client =
-                    client_map.entry(client).or_insert(Client::new(client, 0.));
-
-                if let Err(e) = client.add(amount) {
-                    warn!("{:?}", e);
-                } else {
-                    if tx_map.contains_key(&tx) {
-                        warn!(
-                            "another transaction {:?} already exists with \
-                            tx id {}. Ignoring this entry",
-                            tx_map.get(&tx).unwrap(),
-                            tx
-                        );
-                    } else {
-                        tx_map.insert(tx, tx_entry);
-                    }
+                let csv = csv.options();
+                if let Err(e) =
+                    convert::convert(input, *from, &csv, output, *to)
+                {
+                    eprintln!("error: {}", e);
+                    process::exit(1);
                 }
             }
-            Transaction::Withdrawal {
+            Command::Statement {
+                input,
                 client,
-                tx,
-                amount,
-                dispute: _,
+                input_format,
+                csv,
+                policy,
             } => {
-                let client =
-                    client_map.entry(client).or_insert(Client::new(client, 0.));
-
-                if let Err(e) = client.rm(amount) {
-                    warn!("{:?}", e);
-                } else {
-                    if tx_map.contains_key(&tx) {
-                        warn!(
-                            "another transaction {:?} already exists with \
-                            tx id {}. Ignoring this entry",
-                            tx_map.get(&tx).unwrap(),
-                            tx
-                        );
-                    } else {
-                        tx_map.insert(tx, tx_entry);
-                    }
+                let csv = csv.options();
+                if let Err(e) = statement::statement(
+                    input,
+                    *input_format,
+                    &csv,
+                    policy.policy(),
+                    *client,
+                ) {
+                    eprintln!("error: {}", e);
+                    process::exit(1);
                 }
             }
-
-            // Initialize a dispute. Client wants to reverse a withdrawal or 
-            // a deposit
-            Transaction::Dispute { client, tx } => {
-                let client = match client_map.get_mut(&client) {
-                    Some(client) => client,
-                    None => {
-                        warn!(
-                            "a new client with no transaction \
-                            history cannot have a dispute"
-                        );
-                        continue;
+            Command::Validate {
+                input,
+                snapshot,
+                max_error_rate,
+                input_format,
+                csv,
+                policy,
+            } => {
+                let csv = csv.options();
+                let report = match validate::validate(
+                    input,
+                    *input_format,
+                    &csv,
+                    policy.policy(),
+                    snapshot.as_deref(),
+                ) {
+                    Ok(report) => report,
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        process::exit(1);
                     }
                 };
 
-                if client.is_locked() {
-                    continue;
+                for problem in &report.problems {
+                    println!("{}", problem);
                 }
-
-                match tx_map.get_mut(&tx) {
-                    Some(root_tx) => {
-                        match root_tx {
-                            Transaction::Deposit {
-                                client: root_id,
-                                tx: root_tx,
-                                amount,
-                                dispute,
-                            } => {
-                                if *root_id != client.id() {
-                                    warn!(
-                                        "dispute transaction is referring to a \
-                                        transaction owned by another client: \
-                                        {:?}",
-                                        tx_entry
-                                    );
-                                    continue;
-                                }
-
-                                assert_eq!(
-                                    *root_tx, tx,
-                                    "expected dispute tx id to equal the id \
-                                    of the tx being referred to. Transactions \
-                                    might be stored in tx_map wrong"
-                                );
-
-                                if !*dispute {
-                                    *dispute = true;
-                                } else {
-                                    warn!(
-                                        "tx {} is already being disputed: {:?}",
-                                        tx, root_tx
-                                    );
-                                    continue;
-                                }
-
-                                // do not panic on deposit 1, 
-                                // withdraw 1, dispute the deposit. Just log it
-                                if let Err(e) = client.hold(*amount) {
-                                    warn!(
-                                        "error holding {}: {:?}",
-                                        *amount, e
-                                    );
-                                }
-                            }
-                            Transaction::Withdrawal {
-                                client: root_id,
-                                tx: root_tx,
-                                amount,
-                                dispute,
-                            } => {
-                                if *root_id != client.id() {
-                                    warn!(
-                                        "dispute transaction is referring to a \
-                                        transaction owned by another client: \
-                                        {:?}",
-                                        tx_entry
-                                    );
-                                    continue;
-                                }
-
-                                assert_eq!(
-                                    *root_tx, tx,
-                                    "expected dispute tx id to equal the id \
-                                    of the tx being referred to. Transactions \
-                                    might be stored in tx_map wrong"
-                                );
-
-                                if !*dispute {
-                                    *dispute = true;
-                                } else {
-                                    warn!(
-                                        "tx {} is already being disputed: {:?}",
-                                        tx, root_tx
-                                    );
-                                    continue;
-                                }
-
-                                if let Err(e) = client.add(*amount) {
-                                    warn!(
-                                        "error adding {}: {:?}",
-                                        *amount, e
-                                    );
-                                } else {
-                                    client.hold(*amount).unwrap();
-                                }
-                            }
-                            _ => panic!(
-                                "expected root transaction with id {} \
-                                to be a deposit or withdrawal type. tx_map
-                                bug",
-                                tx
-                            ),
-                        };
-                    }
-                    None => warn!(
-                        "transaction {} for \
-                        client {} does not exist: {:?}", 
-                        tx, client.id(), tx_entry
-                    ),
+                eprintln!(
+                    "{} of {} rows have problems ({:.2}%)",
+                    report.problems.len(),
+                    report.rows,
+                    100. * report.error_rate()
+                );
+                if report.error_rate() > *max_error_rate {
+                    process::exit(1);
                 }
             }
-
-            // The client loses. No-op the dispute and return the funds
-            // to their former state
-            Transaction::Resolve { client, tx } => {
-                let client = match client_map.get_mut(&client) {
-                    Some(client) => client,
-                    None => {
-                        warn!(
-                            "a new client with no transaction \
-                            history cannot have a dispute"
-                        );
-                        continue;
+            Command::Reconcile {
+                actual,
+                expected,
+                run,
+                tolerance,
+                opening_balances,
+                snapshot,
+                input_format,
+                csv,
+                policy,
+            } => {
+                let csv = csv.options();
+                let actual = if *run {
+                    reconcile::Actual::Run {
+                        input: actual,
+                        from: *input_format,
+                        csv: &csv,
+                        policy: policy.policy(),
+                        opening_balances: opening_balances.as_deref(),
+                        snapshot: snapshot.as_deref(),
                     }
+                } else {
+                    reconcile::Actual::Output(actual)
                 };
-
-                if client.is_locked() {
-                    continue;
-                }
-
-                match tx_map.get_mut(&tx) {
-                    Some(root_tx) => match root_tx {
-                        Transaction::Deposit {
-                            client: root_id,
-                            tx: root_tx,
-                            amount,
-                            dispute,
-                        } => {
-                            if *root_id != client.id() {
-                                warn!(
-                                    "dispute transaction is referring to a \
-                                    transaction owned by another client: \
-                                    {:?}",
-                                    tx_entry
-                                );
-                                continue;
-                            }
-
-                            assert_eq!(
-                                *root_tx, tx,
-                                "expected dispute tx id to equal the id \
-                                    of the tx being referred to. Transactions \
-                                    might be stored in tx_map wrong"
-                            );
-
-                            if *dispute {
-                                *dispute = false;
-                            } else {
-                                warn!(
-                                    "no dispute to resolve: {:?}", 
-                                    tx_entry
-                                );
-                                continue;
-                            }
-
-                            // do not panic on deposit 1, 
-                            // withdraw 1, dispute deposit. Just log it
-                            if let Err(e) = client.unhold(*amount) {
-                                warn!(
-                                    "should be enough held funds in {:?} to \
-                                    unhold {}: {:?}",
-                                    client, *amount, e
-                                );
-                            }
+                let report =
+                    match reconcile::reconcile(actual, expected, *tolerance) {
+                        Ok(report) => report,
+                        Err(e) => {
+                            eprintln!("error: {}", e);
+                            process::exit(1);
                         }
-                        Transaction::Withdrawal {
-                            client: root_id,
-                            tx: root_tx,
-                            amount,
-                            dispute,
-                        } => {
-                            if *root_id != client.id() {
-                                warn!(
-                                    "dispute transaction is referring to a \
-                                    transaction owned by another client: \
-                                    {:?}",
-                                    tx_entry
-                                );
-                                continue;
-                            }
-
-                            assert_eq!(
-                                *root_tx, tx,
-                                "expected dispute tx id to equal the id \
-                                    of the tx being referred to. Transactions \
-                                    might be stored in tx_map wrong"
-                            );
+                    };
 
-                            if *dispute {
-                                *dispute = false;
-                            } else {
-                                warn!(
-                                    "no dispute to resolve: {:?}",
-                                    tx_entry
-                                );
-                                continue;
-                            }
-
-                            // this must panic b.c. we added funds in to hold
-                            // at the initial dispute as a way of saying
-                            // "let's pretend the withdrawal never happened
-                            // for now until the dispute is settled"
-                            if let Err(e) = client.unhold(*amount) {
-                                panic!(
-                                    "error unholding {}: {:?}, \
-                                    for client {:?}. Initial dispute bug",
-                                    *amount, e, client
-                                );
-                            } else {
-                                if let Err(e) = client.rm(*amount) {
-                                    panic!(
-                                        "should be no error removing {} \
-                                        from client {}: {:?}. Initial dispute \
-                                        bug",
-                                        *amount,
-                                        client.id(),
-                                        e
-                                    );
-                                }
-                            }
-                        }
-                        _ => panic!(
-                            "expected root transaction with id {} \
-                            to be a deposit or withdrawal type. Transactions \
-                            in the tx_map must never be a referring type. \
-                            tx_map bug",
-                            tx
-                        ),
-                    },
-                    None => warn!("transaction {} does not exist", tx),
+                for brk in &report.breaks {
+                    println!("{}", brk);
+                }
+                eprintln!(
+                    "{} breaks across {} clients",
+                    report.breaks.len(),
+                    report.clients
+                );
+                if !report.is_clean() {
+                    process::exit(1);
                 }
             }
-
-            // The client wins. Give them their money directly and lock the
-            // compromised account
-            Transaction::Chargeback { client, tx } => {
-                let client = match client_map.get_mut(&client) {
-                    Some(client) => client,
-                    None => {
-                        warn!(
-                            "a new client with no transaction \
-                            history cannot have a dispute"
-                        );
-                        continue;
+            Command::Explain {
+                input,
+                client,
+                input_format,
+                csv,
+                policy,
+            } => {
+                let csv = csv.options();
+                if let Err(e) = explain::explain(
+                    input,
+                    *input_format,
+                    &csv,
+                    policy.policy(),
+                    *client,
+                ) {
+                    eprintln!("error: {}", e);
+                    process::exit(1);
+                }
+            }
+            Command::Diff {
+                before,
+                after,
+                disputes,
+                json,
+            } => {
+                let diff = match diff::diff(before, after, *disputes) {
+                    Ok(diff) => diff,
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        process::exit(2);
                     }
                 };
 
-                if client.is_locked() {
-                    continue;
+                if *json {
+                    serde_json::to_writer_pretty(io::stdout(), &diff).unwrap();
+                    println!();
+                } else {
+                    print!("{}", diff);
                 }
-
-                match tx_map.get_mut(&tx) {
-                    Some(root_tx) => {
-                        match root_tx {
-                            Transaction::Deposit { 
-                                client: root_id, 
-                                tx: root_tx, 
-                                amount, 
-                                dispute 
-                            } => {
-                                if *root_id != client.id() {
-                                    warn!(
-                                        "dispute transaction is referring to a \
-                                        transaction owned by another client: \
-                                        {:?}",
-                                        tx_entry
-                                    );
-                                    continue;
-                                }
-
-                                assert_eq!(
-                                    *root_tx, tx,
-                                    "expected dispute tx id to equal the id \
-                                    of the tx being referred to. Transactions \
-                                    might be stored in tx_map wrong"
-                                );
-
-                                if *dispute {
-                                    *dispute = false;
-                                } else {
-                                    warn!(
-                                        "no dispute to resolve: {:?}",
-                                        tx_entry
-                                    );
-                                    continue;
-                                }
-
-                                // deposit 1, withdraw 1, dispute deposit 
-                                // success would result in negative balance,
-                                // and fail on unhold. Log this
-                                if let Err(e) = client.unhold(*amount) {
-                                    warn!(
-                                        "{:?} should have enough held funds \
-                                        from initial dispute to unhold {}: \
-                                        {:?}",
-                                        client,
-                                        *amount,
-                                        e
-                                    );
-                                }
-
-                                if let Err(e) = client.rm(*amount) {
-                                    warn!(
-                                        "{:?} should have enough funds to undo \
-                                        deposit of {}: {:?}", 
-                                        client, 
-                                        *amount,
-                                        e
-                                    );
-                                }
-
-                                client.lock();
-                            }
-                            Transaction::Withdrawal { 
-                                client: root_id, 
-                                tx: root_tx, 
-                                amount, 
-                                dispute 
-                            } => {
-                                if *root_id != client.id() {
-                                    warn!(
-                                        "dispute transaction is referring to a \
-                                        transaction owned by another client: \
-                                        {:?}",
-                                        tx_entry
-                                    );
-                                    continue;
-                                }
-
-                                assert_eq!(
-                                    *root_tx, tx,
-                                    "expected dispute tx id to equal the id \
-                                    of the tx being referred to. Transactions \
-                                    might be stored in tx_map wrong"
-                                );
-
-                                if *dispute {
-                                    *dispute = false;
-                                } else {
-                                    warn!(
-                                        "no dispute to resolve: {:?}",
-                                        tx_entry
-                                    );
-                                    continue;
-                                }
-
-                                // we added funds on initial dispute to hold as
-                                // a way of saying "ok, let's pretend this
-                                // withdrawal never happened for now until 
-                                // dispute is settled". unhold()/rm() should 
-                                // never fail
-                                if let Err(e) = client.unhold(*amount) {
-                                    panic!(
-                                        "{:?} should have enough held funds \
-                                        from initial dispute to unhold {}: \
-                                        {:?}",
-                                        client,
-                                        *amount,
-                                        e
-                                    );
-                                }
-
-                                if let Err(e) = client.rm(*amount) {
-                                    panic!(
-                                        "{:?} should have enough funds to undo \
-                                        withdrawal of {}: {:?}", 
-                                        client, 
-                                        *amount,
-                                        e
-                                    );
-                                }
-
-                                client.lock();
-                            }
-                            _ => panic!(
-                                "expected root transaction with id {} \
-                                to be a deposit or withdrawal type. Transactions \
-                                in the tx_map must never be a referring type",
-                                tx
-                            ),
-                        }
-                    },
-                    None => warn!("transaction {} does not exist", tx),
+                if !diff.is_empty() {
+                    process::exit(1);
                 }
             }
         }
+        return;
     }
 
-    let mut wtr = csv::Writer::from_writer(io::stdout());
-    for client in client_map.values() {
-        wtr.serialize(client).unwrap();
+    let tx_path = match &args.tx_csv {
+        Some(path) => path,
+        None => clap::Error::with_description(
+            "The following required arguments were not provided:\n    \
+            <tx-csv>",
+            clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    };
+
+    match args.store {
+        StoreKind::Map => run(&args, tx_path, HashMap::<u16, Client>::new()),
+        StoreKind::Vec => run(&args, tx_path, VecStore::new()),
+        StoreKind::File => {
+            // --store is case-insensitive, which required_if is not
+            let path = match &args.store_path {
+                Some(path) => path,
+                None => clap::Error::with_description(
+                    "The following required arguments were not provided:\n    \
+                    --store-path <store-path>",
+                    clap::ErrorKind::MissingRequiredArgument,
+                )
+                .exit(),
+            };
+            run(&args, tx_path, FileStore::open(path).unwrap())
+        }
     }
 }
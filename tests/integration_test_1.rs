@@ -1,3 +1,5 @@
+#![allow(clippy::single_component_path_imports, clippy::map_entry)]
+
 use csv;
 use floof::{
     client::Client,
@@ -68,7 +70,11 @@ fn integration_test_1() {
 
             // Initialize a dispute. Client wants to reverse a withdrawal or 
             // a deposit
-            Transaction::Dispute { client, tx } => {
+            Transaction::Dispute {
+                client,
+                tx,
+                amount: _,
+            } => {
                 let client = match client_map.get_mut(&client) {
                     Some(client) => client,
                     None => {
@@ -188,7 +194,11 @@ fn integration_test_1() {
 
             // The client loses. No-op the dispute and return the funds
             // to their former state
-            Transaction::Resolve { client, tx } => {
+            Transaction::Resolve {
+                client,
+                tx,
+                amount: _,
+            } => {
                 let client = match client_map.get_mut(&client) {
                     Some(client) => client,
                     None => {
@@ -317,7 +327,11 @@ fn integration_test_1() {
 
             // The client wins. Give them their money directly and lock the
             // compromised account
-            Transaction::Chargeback { client, tx } => {
+            Transaction::Chargeback {
+                client,
+                tx,
+                amount: _,
+            } => {
                 let client = match client_map.get_mut(&client) {
                     Some(client) => client,
                     None => {
@@ -467,6 +481,10 @@ fn integration_test_1() {
                     None => println!("transaction {} does not exist", tx),
                 }
             }
+            Transaction::Authorize { .. }
+            | Transaction::Capture { .. }
+            | Transaction::Void { .. }
+            | Transaction::Admin { .. } => (),
         }
     }
 
@@ -485,8 +503,8 @@ fn integration_test_1() {
         println!("{}", line);
     }
 
-    assert_eq!(lines[0], "client,available,held,total,locked");
-    assert_eq!(lines[1], "1,1.0,0.0,1.0,false");
-    assert_eq!(lines[2], "2,6.0,0.0,6.0,true");
-    assert_eq!(lines[3], "3,20.0,0.0,20.0,false");
+    assert_eq!(lines[0], "client,available,held,total,locked,status");
+    assert_eq!(lines[1], "1,1.0,0.0,1.0,false,active");
+    assert_eq!(lines[2], "2,6.0,0.0,6.0,true,locked");
+    assert_eq!(lines[3], "3,20.0,0.0,20.0,false,active");
 }
@@ -0,0 +1,141 @@
+use floof::{
+    client::Client,
+    store::{ClientStore, FileStore, VecStore},
+};
+use float_cmp::approx_eq;
+use std::{collections::HashMap, env, fs, path::PathBuf, process};
+
+// Every ClientStore implementation must pass these checks. Add new stores to
+// the bottom of this file
+
+fn empty<S: ClientStore>(store: S) {
+    assert!(store.is_empty());
+    assert_eq!(store.len(), 0);
+    assert!(store.get(1).is_none());
+    assert!(!store.contains(1));
+    assert_eq!(store.iter().count(), 0);
+}
+
+fn insert_get<S: ClientStore>(mut store: S) {
+    assert!(store.insert(Client::new(1, 10.)).is_none());
+    assert!(store.insert(Client::new(0, 1.)).is_none());
+    assert!(store.insert(Client::new(u16::MAX, 2.)).is_none());
+    assert_eq!(store.len(), 3);
+    assert!(!store.is_empty());
+
+    for id in [0, 1, u16::MAX].iter() {
+        assert!(store.contains(*id));
+        assert_eq!(store.get(*id).unwrap().id(), *id);
+    }
+    assert!(approx_eq!(
+        f64,
+        store.get(1).unwrap().available(),
+        10.,
+        ulps = 1
+    ));
+    assert!(store.get(2).is_none());
+}
+
+fn insert_replaces<S: ClientStore>(mut store: S) {
+    store.insert(Client::new(7, 1.));
+    let prev = store.insert(Client::new(7, 2.)).unwrap();
+    assert!(approx_eq!(f64, prev.available(), 1., ulps = 1));
+    assert_eq!(store.len(), 1);
+    assert!(approx_eq!(
+        f64,
+        store.get(7).unwrap().available(),
+        2.,
+        ulps = 1
+    ));
+}
+
+fn get_mut<S: ClientStore>(mut store: S) {
+    assert!(store.get_mut(3).is_none());
+    store.insert(Client::new(3, 5.));
+    store.get_mut(3).unwrap().rm(2.).unwrap();
+    store.get_mut(3).unwrap().lock();
+
+    let client = store.get(3).unwrap();
+    assert!(approx_eq!(f64, client.available(), 3., ulps = 1));
+    assert!(client.is_locked());
+}
+
+fn get_or_create<S: ClientStore>(mut store: S) {
+    store.get_or_create(4).add(1.5).unwrap();
+    store.get_or_create(4).add(1.5).unwrap();
+    assert_eq!(store.len(), 1);
+    assert!(approx_eq!(
+        f64,
+        store.get(4).unwrap().total(),
+        3.,
+        ulps = 1
+    ));
+}
+
+fn iterate<S: ClientStore>(mut store: S) {
+    for id in (0..100).rev() {
+        store.insert(Client::new(id * 3, f64::from(id)));
+    }
+
+    let mut ids: Vec<u16> = store.iter().map(|client| client.id()).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, (0..100).map(|id| id * 3).collect::<Vec<u16>>());
+}
+
+fn conformance<S: ClientStore, F: Fn() -> S>(new: F) {
+    empty(new());
+    insert_get(new());
+    insert_replaces(new());
+    get_mut(new());
+    get_or_create(new());
+    iterate(new());
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("floof_{}_{}.csv", name, process::id()))
+}
+
+#[test]
+fn hash_map_store() {
+    conformance(HashMap::<u16, Client>::new);
+}
+
+#[test]
+fn vec_store() {
+    conformance(VecStore::new);
+}
+
+#[test]
+fn file_store() {
+    let path = temp_path("file_store_conformance");
+    conformance(|| {
+        let _ = fs::remove_file(&path);
+        FileStore::open(&path).unwrap()
+    });
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn file_store_persists() {
+    let path = temp_path("file_store_persists");
+    let _ = fs::remove_file(&path);
+
+    let mut store = FileStore::open(&path).unwrap();
+    store.get_or_create(1).add(4.).unwrap();
+    store.get_or_create(1).hold(1.).unwrap();
+    store.get_or_create(2).lock();
+    store.insert(Client::new(3, 1.).with_debt(2.));
+    store.flush().unwrap();
+
+    let store = FileStore::open(&path).unwrap();
+    assert_eq!(store.len(), 3);
+    let client = store.get(1).unwrap();
+    assert!(approx_eq!(f64, client.available(), 3., ulps = 1));
+    assert!(approx_eq!(f64, client.held(), 1., ulps = 1));
+    assert!(approx_eq!(f64, client.total(), 4., ulps = 1));
+    assert!(store.get(2).unwrap().is_locked());
+    assert_eq!(store.get(3).unwrap().debt(), 2.);
+    assert_eq!(store.get(3).unwrap().total(), -1.);
+
+    fs::remove_file(&path).unwrap();
+}